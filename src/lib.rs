@@ -0,0 +1,5875 @@
+//! Parse the JSON file for Vivaldi notes and find notes in it by their
+//! metadata. `NotesTree` is the interface for other programs; `run` is the
+//! command line tool built on the same traversals.
+use std::cell::Cell;
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Seek, Write};
+use std::ops::ControlFlow;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{self, ExitCode};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, Command, ValueHint};
+use clap_complete::Shell;
+use rand::{rngs::StdRng, SeedableRng};
+use regex::{Regex, RegexBuilder};
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::{self, json, Map, Value};
+use unicode_segmentation::UnicodeSegmentation;
+use sha2::{Digest, Sha256};
+
+/// The options of the command line, shared by all subcommands.
+fn options() -> Vec<Arg> {
+    let flag = |name: &'static str, help: &'static str| Arg::new(name).long(name).help(help).action(ArgAction::SetTrue);
+    // values may start with a dash, e.g. --min -5 or -c "- [ ]"
+    let value = |name: &'static str, value_name: &'static str, help: &'static str| {
+        Arg::new(name).long(name).value_name(value_name).help(help).allow_hyphen_values(true)
+    };
+    let number = |name, value_name, help| value(name, value_name, help).value_parser(value_parser!(usize));
+    vec![
+        value("key", "key", "Select the note with this key, repeated with a -v or -c each for notes that match all of them, e.g.: -k subject -v Todo -k type -v note").short('k').action(ArgAction::Append),
+        value("value", "value", "Select the note with this chosen key and this value, e.g.: -k id -v 456").short('v').action(ArgAction::Append),
+        value("contains", "contents", "Select the note with this chosen key and contains the given contents, e.g.: -k contents -c \"Some content\"").short('c').action(ArgAction::Append),
+        value("query", "expression", "Select the notes for which this expression holds, comparing fields with == (equals), !=, ~ (contains) and !~, combined with && or and, || or or, ! or not and parentheses, e.g.: --query 'subject==\"Todo\" && (content~urgent || content~asap)'"),
+        value("regex", "pattern", "Select the note with this chosen key and a value matching the regular expression, e.g.: -k subject -r '^2024-\\d{2}'").short('r'),
+        flag("ignore-case", "Match -v, -c and -r ignoring case, non-ASCII letters included, e.g.: -i -k subject -v todo").short('i'),
+        flag("any-field", "Match -v or -c against every field of the note instead of the one chosen with -k, e.g.: --any-field -c Todo"),
+        flag("combined-field", "Match -v or -c against the subject and content joined by a newline, so that -c can find text running from the end of the subject into the content, which --any-field cannot, e.g.: --combined-field -c \"Shopping\\nmilk\""),
+        flag("raw-match", "Match -v and -c against the json-escaped values, so that escapes are compared literally rather than as the characters they stand for, e.g.: --raw-match -k subject -c '\\n'"),
+        flag("normalize-whitespace", "Collapse runs of whitespace and non-breaking spaces to one space in the values and in -v or -c before matching, the output is left as it is, e.g.: --normalize-whitespace -c 'Meeting notes'"),
+        flag("word", "Only match -c on whole words, e.g.: -c cat matches \"a cat sat\" but not \"category\""),
+        flag("invert", "Select the notes that do not match the whole query instead, e.g.: --all --invert -k subject -c Archive"),
+        // counted, as each --not belongs to the -k before it
+        flag("not", "Select the notes whose field chosen by the -k before it does not match its -v or -c, unlike --invert the rest of the query still has to match, e.g.: search -k type -v note -k subject -c draft --not").action(ArgAction::Count),
+        value("print-key", "key", "Print this field of the matched note instead of its content, e.g.: -k content -c Todo --print-key id"),
+        value("output-field", "field", "Print these fields of the matched note, separated by tabs or as a json object with --json, may be repeated, e.g.: --output-field id --output-field date_added").action(ArgAction::Append),
+        flag("children-only", "Match folders too and print the subjects of the direct children of the matched note, one per line, like ls, e.g.: -k subject -v Work --children-only"),
+        flag("child-count", "Match folders too and print the number of direct children of the matched note, e.g.: -k subject -v Work --child-count"),
+        flag("format-content", "Pretty-print the content of matched notes when it is a json object or array"),
+        number("max-content-bytes", "n", "Cut the printed content of matched notes after n bytes, e.g.: --max-content-bytes 4096"),
+        flag("index-path", "Print the positions in the children arrays from the root to the matched note, e.g.: 0/3/1"),
+        flag("depth-of", "Print how deep the matched note is, counting the notes directly under the root as depth 1"),
+        flag("byte-offset", "Print the byte offset in the input file at which the searched field of each match starts, found by searching the raw text in tree order, so a value that also occurs earlier in the file is reported there"),
+        flag("show-path", "Print the subjects of the folders leading to each match and its own before it, separated by a tab, e.g.: Work/Projects/Todo Queue\t..."),
+        flag("dump-path", "Print the JSON Pointer to each matched note and its subject, separated by a tab, for use with --pointer, e.g.: /children/0/children/3\tTodo"),
+        value("template", "template", "Print each matched note with its {field} placeholders filled in, supports \\n and \\t, e.g.: --template '{id}: {subject}\\n{content}'"),
+        flag("escape-newlines", "Print newlines, tabs and backslashes in matches as \\n, \\t and \\\\, so that every match takes up one line"),
+        flag("numbered", "Prefix each printed line with its line number, like cat -n"),
+        number("context", "n", "After a match, also list the subjects of up to n notes before and after it in its folder, e.g.: --context 2"),
+        flag("all", "Print every matching note instead of only the first one, separated by --separator or as one array with --json-array, e.g.: -a -k subject -v Todo").short('a'),
+        value("schema", "version", "Read the field names of the notes file of this Vivaldi release, vivaldi5 or vivaldi6, instead of detecting it from the fields present, e.g.: --schema vivaldi5").value_parser(["vivaldi5", "vivaldi6"]),
+        flag("occurrences", "With --all, prefix every match with the number of times the -c text occurs in its content, e.g.: --all --occurrences -k content -c rust"),
+        flag("indent-preview", "Indent the content previews of the summary by two spaces for every folder above their note, e.g.: --indent-preview"),
+        flag("hash", "Add to every note of the summary or of --flatten a hash field, the hex SHA-256 of its subject, a NUL byte and its content, e.g.: --flatten --hash"),
+        flag("group-by-folder", "With --all, print the matches under a heading for the folder they are in, notes at the top level under (root), e.g.: --all --group-by-folder -k content -c TODO"),
+        flag("post-order", "Visit the notes in a folder before the folder itself, which changes the order of matched folders, e.g.: --all --child-count --post-order -k subject -c Work"),
+        number("nth", "n", "Print only the n-th matching note in tree order, counting from 1, e.g.: -k subject -v Todo --nth 2"),
+        flag("first", "Print only the first matching note in tree order, the default, but refuse --all, --sample and --last, e.g.: -k subject -v Todo --first"),
+        flag("last", "Print the last matching note in tree order instead of the first one, e.g.: -k subject -v Todo --last"),
+        value("separator", "string", "Print this between matches with --all instead of a newline, supports \\n, \\t and \\0, e.g.: --separator '\\0'"),
+        number("match-limit-per-file", "n", "With --all, keep only the first n matches of each input file, e.g.: --files-from - --all --match-limit-per-file 1"),
+        flag("rank", "With --all, sort the matches by how often they contain the searched text, subject matches first"),
+        number("sample", "n", "Print n randomly chosen matches in tree order instead of all of them, e.g.: -k content -c Todo --sample 5"),
+        value("seed", "seed", "Seed the random choice of --sample for reproducible output, e.g.: --sample 5 --seed 42").value_parser(value_parser!(u64)),
+        flag("opml", "Print the whole tree as an OPML outline, or with a query the matched notes and folders with everything in them, e.g.: -k subject -v Work --opml"),
+        flag("json-array", "With --all, print the matched notes as a single json array instead of their contents"),
+        flag("json", "Print the whole matched note, with all of its fields and children, as pretty-printed json instead of its content, e.g.: -k id -v 456 --json | jq .subject"),
+        value("exclude-key", "field", "Leave this field out of the notes printed with --json or --json-array, can be repeated, e.g.: --exclude-key attachment --exclude-key date_added").action(ArgAction::Append),
+        flag("no-children-in-full", "Leave the children out of the notes printed with --json or --json-array, so that folders only show their own fields"),
+        flag("unique", "With --all, print identical matches only once, in the order they were first found"),
+        value("output-dir", "dir", "With --all, write each match to its own file in this directory, named after its subject or id, and print the file names").value_hint(ValueHint::DirPath),
+        value("since", "duration", "Only match notes modified within the given time, e.g.: --since 7d, --since 2h, --since 1d12h (units: d, h, m), works without -k too"),
+        value("after", "date", "Only match and summarize notes added at or after this ISO date or time in UTC, e.g.: --after 2024-01-01 or --after 2024-01-01T08:30"),
+        value("before", "date", "Only match and summarize notes added before this ISO date or time in UTC, e.g.: --after 2024-01-01 --before 2024-02-01"),
+        value("has", "field", "Only match notes that have this field with a value other than null, works without -k too, e.g.: --has attachmentId"),
+        value("min", "number", "Match notes whose -k field is a number of at least this value, instead of using -v or -c, e.g.: -k priority --min 2").value_parser(value_parser!(f64)),
+        value("max", "number", "Match notes whose -k field is a number of at most this value, e.g.: -k priority --min 2 --max 5").value_parser(value_parser!(f64)),
+        number("min-len", "n", "Only match notes whose content is at least n characters long, works without -k too, e.g.: --min-len 5000"),
+        number("max-len", "n", "Only match notes whose content is at most n characters long, works without -k too, e.g.: --max-len 0"),
+        value("fields", "fields", "Show these fields of every note in the summary instead of id, subject and content, separated by commas, e.g.: --fields id,subject,date_added"),
+        number("depth", "n", "Only show n levels of folders below the root in the summary, the children of the deepest ones are counted in an omitted entry, e.g.: --depth 2"),
+        number("head", "n", "Only show the first n children of each folder in the summary, e.g.: --head 5"),
+        number("tail", "n", "Only show the last n children of each folder in the summary, can be combined with --head"),
+        number("truncate", "n", "Cut the subjects and contents in the summary after n characters instead of 30, 0 shows them in full, e.g.: --truncate 80"),
+        number("truncate-subject", "n", "Cut only the subjects in the summary after n characters, e.g.: --truncate-subject 0"),
+        number("truncate-content", "n", "Cut only the contents in the summary after n characters, e.g.: --truncate-content 10"),
+        flag("compact-empty", "Leave null and empty fields out of the summary"),
+        flag("yaml", "Print the summary, --pointer, --normalize and --merge output as YAML instead of json"),
+        value("root-path", "pointer", "Use the note at this JSON Pointer as the root of the tree, for backups that wrap it, e.g.: --root-path /notes"),
+        value("skip-folder", "folder", "Leave out the folders with this subject or id, and everything in them, from the search, the summary and --flatten, may be repeated, e.g.: --skip-folder Trash").action(ArgAction::Append),
+        value("root", "folder", "Only search within the first folder, in tree order, with this subject or id, e.g.: --root Work -k subject -c Todo"),
+        value("path", "path", "Print the note found by following the subjects of its folders and its own, separated by /, instead of searching; when several folders share a subject each of them is tried, e.g.: --path \"Work/Projects/Todo Queue\""),
+        value("pointer", "pointer", "Print the value at this JSON Pointer instead of searching, e.g.: --pointer /children/0/content"),
+        flag("normalize", "Print the whole notes file with sorted keys and consistent indentation, for diffing backups"),
+        flag("branching", "Print the maximum and average number of children per folder and the id of the widest folder"),
+        value("files-from", "list", "Run the query on every file listed in this file, separated by NUL bytes, or read the list from stdin with -, e.g.: find . -name '*Notes.bak' -print0 | vivaldi_notes_parser --files-from - -k subject -c Todo").value_hint(ValueHint::FilePath),
+        flag("strict-json", "Fail on objects with duplicate keys, which are otherwise silently parsed keeping the last value, and on data after the notes"),
+        flag("ignore-missing-file", "Warn about inputs that cannot be read or parsed and go on with the others, only failing when all of them do, also --keep-going").alias("keep-going"),
+        flag("require-all-files", "Check that every input can be opened before searching any of them, and fail right away if one cannot"),
+        value("zip", "archive", "Read the notes from an entry of this zip archive instead of a file").value_hint(ValueHint::FilePath),
+        value("entry", "name", "The entry of the --zip archive to read, by default the only entry named Notes, e.g.: --zip backup.zip --entry Default/Notes"),
+        value("extract-attachments", "dir", "Decode the base64 data of every attachment into its own file in this directory and print the file names, attachments without embedded data are reported on stderr").value_hint(ValueHint::DirPath),
+        flag("flatten", "Print every note without the folders as one json array, --exclude-key applies to them"),
+        flag("with-path", "With --flatten, add a path field with the subjects of the folders of each note, e.g.: \"path\": \"Work/Projects\""),
+        flag("keys", "Print every distinct field name used in the notes file, sorted, to find out what can be searched with -k"),
+        flag("folder-counts", "Print the number of notes under each top-level folder, largest first"),
+        number("top-longest", "n", "Print the n notes with the longest content and their length in characters, longest first, e.g.: --top-longest 10"),
+        value("replace", "from to", "Replace this text in the content of every note the query matches and write the notes back to the input file, e.g.: -k subject -v Todo --replace '[ ]' '[x]'").num_args(2).value_names(["from", "to"]),
+        value("output", "file", "With --replace, write the edited notes to this file instead of the input file").value_hint(ValueHint::FilePath),
+        flag("dry-run", "With --replace, only print how many replacements would be made"),
+        value("merge", "file", "Merge the notes of this file into the input and print the merged tree, notes sharing an id keep the latest modified one").value_hint(ValueHint::FilePath),
+        value("serve", "socket", "Parse the file once, then answer queries sent to this unix socket, one query line per connection, e.g.: --serve /tmp/notes.sock Notes"),
+        flag("repl", "Parse the file once, then read queries line by line from stdin, e.g.: -k subject -c Todo, or shorter: k subject c Todo"),
+        value("error-format", "format", "Report errors on stderr as text (default) or as a json object, e.g.: --error-format json").value_parser(["text", "json"]),
+        value("color", "when", "Color the labels of errors and warnings on stderr: auto (default) only does on a terminal without NO_COLOR set, always or never").value_parser(["auto", "always", "never"]),
+        flag("progress", "Report the number of visited notes on stderr while traversing (only when stderr is a terminal)"),
+        flag("verbose", "Trace every visited note on stderr with whether it matched and why not, e.g.: -V -k subject -v Todo").short('V'),
+        flag("pager", "Show the results through $PAGER or less, which by default only happens on a terminal when they do not fit on it").overrides_with("no-pager"),
+        flag("no-pager", "Never show the results through a pager").overrides_with("pager"),
+        flag("timing", "Report on stderr how many milliseconds reading, parsing and traversing the notes took"),
+    ]
+}
+
+/// The command line: the options work on their own, as before the
+/// subcommands, or after one of them.
+fn command() -> Command {
+    let file = || Arg::new("file").help("The notes file, - for stdin").value_hint(ValueHint::FilePath);
+    let subcommand = |name: &'static str, about: &'static str| Command::new(name).about(about).args_override_self(true).arg(file());
+    let mut after_help = format!("Without a file argument the notes are read from the file named by {NOTES_FILE_VAR}, if it is set, then from the notes_file of the config file and from stdin otherwise. A file argument of - always reads stdin.\n\n");
+    after_help.push_str("The config file ~/.config/vivaldi_notes_parser/config.toml, or the one in $XDG_CONFIG_HOME, sets defaults for options that are not given, e.g.:\n  notes_file = \"~/.config/vivaldi/Default/Notes\"\n  format = \"yaml\"\n  truncate = 40\n  color = \"never\"\n\n");
+    after_help.push_str("If no options are selected, the parser will print a summary by traversing the notes tree with these fields: {id, subject, content[:20], children}\n");
+    after_help.push_str("The exit status is 1 when no note matches or the pointer does not exist, 3 when the input cannot be read and 4 when it is not valid json, or 5 when --ignore-missing-file skipped some of the inputs.\n");
+    after_help.push_str("Invalid arguments print this message to stderr and exit with status 2.\n\n");
+    after_help.push_str("Examples:\n");
+    after_help.push_str("  vivaldi_notes_parser -k id -v 456 Notes\n");
+    after_help.push_str("  vivaldi_notes_parser search -k subject -c Todo Notes\n");
+    after_help.push_str("  cat 2022.01.07_21.00.01_Notes.bak | vivaldi_notes_parser get -k subject -v \"Todo Queue\"");
+    Command::new("vivaldi_notes_parser")
+        .about("Parse the JSON file for Vivaldi notes and print the notes matching the query")
+        .version(env!("CARGO_PKG_VERSION"))
+        // -V is --verbose
+        .disable_version_flag(true)
+        .args_override_self(true)
+        .arg(Arg::new("version").long("version").help("Print the version").action(ArgAction::Version))
+        .args(options().into_iter().map(|arg| arg.global(true)))
+        .arg(file())
+        .subcommand(subcommand("get", "Print the first note matching the query, e.g.: get -k subject -v Todo"))
+        .subcommand(subcommand("search", "Print every note matching the query, like --all, e.g.: search -k subject -c Todo"))
+        .subcommand(subcommand("summary", "Print the summary of the notes tree, as without a query"))
+        .subcommand(subcommand("export", "Print the whole notes tree in another format, e.g.: export --format opml")
+            .arg(Arg::new("format").long("format").help("The format to print the tree in").value_parser(["json", "yaml", "opml", "flat"]).default_value("json")))
+        .subcommand(Command::new("completions").about("Print the completion script for a shell, e.g.: completions bash > /etc/bash_completion.d/vivaldi_notes_parser")
+            .arg(Arg::new("shell").help("The shell to complete in").value_parser(value_parser!(Shell)).required(true)))
+        .after_help(after_help)
+}
+
+/// Print the help of the command line.
+fn usage(out: &mut dyn Write) -> io::Result<()> {
+    write!(out, "{}", command().render_help())
+}
+
+// only built once per run, so the size of the options does not matter;
+// BadArgs reads better than a shortened name next to Help and Key
+#[allow(clippy::large_enum_variant, clippy::enum_variant_names)]
+enum Args {
+    /// -h/--help was given explicitly, with the help to print.
+    Help(String),
+    /// --version was given, with the version to print.
+    Version(String),
+    /// The completions subcommand, for this shell.
+    Completions(Shell),
+    /// The arguments could not be used, with the reason why.
+    BadArgs(String),
+    Key {
+        key: Option<String>,
+        val: Option<String>,
+        contains: Option<String>,
+        input: Input,
+        opts: Options,
+    },
+}
+
+/// Options that tweak how the traversal runs without changing what it matches.
+#[derive(Default)]
+struct Options {
+    error_format: ErrorFormat,
+    /// Whether stderr is colored, auto unless chosen with --color or in the
+    /// config file.
+    color: Option<Color>,
+    any_field: bool,
+    combined_field: bool,
+    word: bool,
+    /// Match the field against this pattern instead of -v or -c.
+    regex: Option<Regex>,
+    /// Compare the values and the searched text, or pattern, ignoring case.
+    ignore_case: bool,
+    /// Match against the values escaped as in json, so that a backslash
+    /// sequence like `\n` can be searched for literally.
+    raw_match: bool,
+    /// Collapse runs of whitespace, non-breaking spaces included, to a single
+    /// space in the values and the searched text before matching.
+    normalize_whitespace: bool,
+    invert: bool,
+    print_key: Option<String>,
+    /// Fields printed for every match, from --output-field.
+    output_fields: Vec<String>,
+    child_count: bool,
+    /// Print the labels of the direct children of the matched note.
+    children_only: bool,
+    max_content_bytes: Option<usize>,
+    format_content: bool,
+    index_path: bool,
+    dump_path: bool,
+    /// Prefix every match with the subjects of its folders and its own.
+    show_path: bool,
+    byte_offset: bool,
+    depth_of: bool,
+    template: Option<String>,
+    numbered: bool,
+    escape_newlines: bool,
+    context: Option<usize>,
+    all: bool,
+    last: bool,
+    /// Only the first match is printed, which is already the default; the
+    /// flag documents that intent and rules out the other result modes.
+    first: bool,
+    /// Print the match at this 1-based position in tree order.
+    nth: Option<usize>,
+    /// Visit the notes in a folder before the folder itself.
+    post_order: bool,
+    group_by_folder: bool,
+    /// Add a `hash` of the subject and content to the summary and flattened
+    /// notes.
+    hash: bool,
+    /// Indent the content previews of the summary by the depth of their note.
+    indent_preview: bool,
+    /// Prefix every match with how often the searched text occurs in its
+    /// content.
+    occurrences: bool,
+    /// The schema of the notes file, detected when not given.
+    schema: Option<Schema>,
+    /// Printed between consecutive matches with `--all`, defaults to a newline.
+    separator: Option<String>,
+    rank: bool,
+    /// Matches kept from each input with `--all`, in tree order.
+    match_limit_per_file: Option<usize>,
+    /// Collects every match like `--all`, then keeps this many at random.
+    sample: Option<usize>,
+    seed: Option<u64>,
+    unique: bool,
+    json_array: bool,
+    /// Print every matched note whole, as pretty-printed json.
+    json: bool,
+    /// Print the tree, or the matched notes with everything in them, as an
+    /// OPML outline.
+    opml: bool,
+    /// Fields removed from the notes that are printed whole.
+    exclude_keys: Vec<String>,
+    no_children_in_full: bool,
+    output_dir: Option<String>,
+    pointer: Option<String>,
+    /// Subjects of the folders leading to the note to print, and its own,
+    /// separated by `/`.
+    note_path: Option<String>,
+    /// JSON Pointer to the root note, for files that wrap the tree.
+    root_path: Option<String>,
+    /// Subject or id of the folder used as the root, found below the one of
+    /// --root-path.
+    root_folder: Option<String>,
+    /// Subjects or ids of the folders left out of the traversals.
+    skip_folders: Vec<String>,
+    normalize: bool,
+    branching: bool,
+    folder_counts: bool,
+    /// Print this many of the notes with the longest content.
+    top_longest: Option<usize>,
+    keys: bool,
+    flatten: bool,
+    with_path: bool,
+    /// Directory to write the embedded attachments to.
+    extract_attachments: Option<String>,
+    merge: Option<String>,
+    /// Text to replace in the content of the matched notes, and its
+    /// replacement.
+    replace: Option<(String, String)>,
+    /// Where --replace writes the edited notes, instead of the input file.
+    output: Option<String>,
+    dry_run: bool,
+    /// Skip inputs that fail to load instead of stopping at the first one.
+    keep_going: bool,
+    /// Check that every input can be opened before searching any of them.
+    require_all_files: bool,
+    strict_json: bool,
+    repl: bool,
+    /// Path of the unix socket to answer queries on.
+    serve: Option<String>,
+    progress: bool,
+    timing: bool,
+    pager: Pager,
+    /// Trace every visited note and why it did or did not match on stderr.
+    verbose: bool,
+    /// Fields of the notes shown in the summary, id, subject and content when
+    /// not given.
+    summary_fields: Option<Vec<String>>,
+    /// Folder levels below the root shown in the summary, deeper children are
+    /// counted in an `{"omitted": n}` entry.
+    max_depth: Option<usize>,
+    /// Children shown per folder in the summary, the rest are counted in an
+    /// `{"omitted": n}` entry.
+    head: Option<usize>,
+    tail: Option<usize>,
+    compact_empty: bool,
+    /// Characters of the subject and content shown in the summary, 0 for
+    /// all of them.
+    truncate_subject: Option<usize>,
+    truncate_content: Option<usize>,
+    /// The format of structured output, from --yaml or export --format. The
+    /// config file only sets it when neither is given.
+    format: Option<OutputFormat>,
+    /// Chrome epoch timestamp; notes modified before it do not match.
+    modified_since: Option<i64>,
+    /// Chrome epoch timestamps bounding when matched notes were added, the
+    /// first inclusive, the second exclusive.
+    added_after: Option<i64>,
+    added_before: Option<i64>,
+    /// A field that matched notes must have, with any value but null.
+    has: Option<String>,
+    /// Inclusive bounds on the number in the -k field.
+    min_value: Option<f64>,
+    max_value: Option<f64>,
+    /// Bounds on the number of characters in the content of a matched note.
+    min_len: Option<usize>,
+    max_len: Option<usize>,
+    /// The -k fields after the first one, which matched notes must have too.
+    predicates: Vec<Predicate>,
+    /// Whether the first -k field, or the field mode, must not match, from
+    /// --not.
+    negated: bool,
+    /// The expression of --query that matched notes must satisfy.
+    expr: Option<Expr>,
+}
+
+#[derive(Clone, Copy, Default)]
+enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+enum Input {
+    File(String),
+    Zip {
+        archive: String,
+        entry: Option<String>,
+    },
+    /// A NUL separated list of files, read from stdin for "-".
+    FilesFrom(String),
+    Stdin,
+}
+
+/// The environment variable naming the notes file to read when no file
+/// argument is given.
+const NOTES_FILE_VAR: &str = "VIVALDI_NOTES_FILE";
+
+/// Defaults for the options, read from `vivaldi_notes_parser/config.toml` in
+/// `$XDG_CONFIG_HOME` or `~/.config`. The command line and the environment
+/// take precedence over it.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// The notes file read when there is no file argument and
+    /// `NOTES_FILE_VAR` is not set, `~/` stands for the home directory.
+    notes_file: Option<String>,
+    /// The format of structured output, like --yaml.
+    format: Option<OutputFormat>,
+    /// Characters of the subject and content shown in the summary, like
+    /// --truncate.
+    truncate: Option<usize>,
+    color: Option<Color>,
+}
+
+#[derive(Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Json,
+    Yaml,
+}
+
+/// When the labels of errors and warnings on stderr are colored.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Color {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Config {
+    fn path() -> Option<PathBuf> {
+        let base = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(base.join("vivaldi_notes_parser").join("config.toml"))
+    }
+
+    /// Read the config file, with the defaults of the options when there is
+    /// none.
+    fn load() -> Result<Config, String> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("invalid config file {}: {}", path.display(), e.message())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(format!("could not read config file {}: {e}", path.display())),
+        }
+    }
+
+    fn notes_file(&self) -> Option<String> {
+        let file = self.notes_file.as_ref()?;
+        match (file.strip_prefix("~/"), env::var("HOME")) {
+            (Some(rest), Ok(home)) => Some(format!("{home}/{rest}")),
+            _ => Some(file.clone()),
+        }
+    }
+
+    /// Fill in the options that were not given on the command line.
+    fn apply(&self, opts: &mut Options) {
+        opts.format = opts.format.or(self.format);
+        opts.truncate_subject = opts.truncate_subject.or(self.truncate);
+        opts.truncate_content = opts.truncate_content.or(self.truncate);
+        opts.color = opts.color.or(self.color);
+    }
+}
+
+/// The label of a message on stderr, with `error` in red and anything else
+/// in yellow when colors are enabled.
+fn label(color: Option<Color>, name: &str) -> String {
+    let enabled = match color.unwrap_or(Color::Auto) {
+        Color::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        Color::Always => true,
+        Color::Never => false,
+    };
+    let code = if name == "error" { 31 } else { 33 };
+    if enabled {
+        format!("\x1b[1;{code}m{name}:\x1b[0m")
+    } else {
+        format!("{name}:")
+    }
+}
+
+/// The --error-format given in the arguments. It is looked for before they
+/// are parsed, so that invalid arguments are reported in that format too.
+fn error_format_arg(args: &[String]) -> ErrorFormat {
+    let mut format = ErrorFormat::Text;
+    for (i, arg) in args.iter().enumerate() {
+        let value = match arg.strip_prefix("--error-format") {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(rest) => rest.strip_prefix('='),
+            None => None,
+        };
+        match value {
+            Some("json") => format = ErrorFormat::Json,
+            Some("text") => format = ErrorFormat::Text,
+            _ => {},
+        }
+    }
+    format
+}
+
+/// Parse the arguments with `command`. The file argument may come anywhere
+/// among the options. Without one the notes are read from `notes_file`, the value of
+/// `NOTES_FILE_VAR`, and then from stdin; a `-` file argument reads stdin
+/// regardless.
+fn parse_args<I>(args: I, notes_file: Option<String>) -> Args
+    where I: Iterator<Item = String>
+{
+    let mut input: Input = Input::Stdin;
+    let mut opts = Options::default();
+
+    let root = match command().try_get_matches_from(args) {
+        Ok(root) => root,
+        Err(e) => match e.kind() {
+            ErrorKind::DisplayHelp => return Args::Help(e.render().to_string()),
+            ErrorKind::DisplayVersion => return Args::Version(e.render().to_string()),
+            _ => {
+                let message = e.render().to_string();
+                let reason = message.lines().next().unwrap_or_default();
+                return Args::BadArgs(String::from(reason.strip_prefix("error: ").unwrap_or(reason)));
+            },
+        },
+    };
+    let (subcommand, matches) = match root.subcommand() {
+        Some(("completions", matches)) => {
+            let shell = matches.get_one::<Shell>("shell").copied().unwrap_or(Shell::Bash);
+            return Args::Completions(shell);
+        },
+        Some((name, matches)) => (Some(name), matches),
+        None => (None, &root),
+    };
+    let string = |name: &str| matches.get_one::<String>(name).cloned();
+    let flag = |name: &str| matches.get_flag(name);
+    let number = |name: &str| matches.get_one::<usize>(name).copied();
+
+    let occurrences = |name: &str| -> Vec<(usize, String)> {
+        match (matches.indices_of(name), matches.get_many::<String>(name)) {
+            (Some(indices), Some(values)) => indices.zip(values.cloned()).collect(),
+            _ => Vec::new(),
+        }
+    };
+    let keys = occurrences("key");
+    // a -v or -c belongs to the last -k before it, or to the first -k
+    let owner = |index: usize| keys.iter().rposition(|(k, _)| *k < index).unwrap_or(0);
+    let mut vals: Vec<Option<String>> = vec![None; keys.len().max(1)];
+    let mut contents: Vec<Option<String>> = vec![None; keys.len().max(1)];
+    let mut negated = vec![false; keys.len().max(1)];
+    // the count has a default value, with an index, even without --not
+    if matches.get_count("not") > 0 {
+        for index in matches.indices_of("not").into_iter().flatten() {
+            negated[owner(index)] = true;
+        }
+    }
+    for (slots, name) in [(&mut vals, "value"), (&mut contents, "contains")] {
+        for (index, value) in occurrences(name) {
+            if slots[owner(index)].replace(value).is_some() {
+                return Args::BadArgs(String::from("each -k takes a single -v or -c"));
+            }
+        }
+    }
+    let mut keys = keys.into_iter().map(|(_, key)| key);
+    let mut vals = vals.into_iter();
+    let mut contents = contents.into_iter();
+    let key = keys.next();
+    let val = vals.next().flatten();
+    let contains = contents.next().flatten();
+    let mut negated = negated.into_iter();
+    opts.negated = negated.next().unwrap_or_default();
+    for (((key, val), contains), negated) in keys.zip(vals).zip(contents).zip(negated) {
+        match (&val, &contains) {
+            (None, None) => return Args::BadArgs(format!("-k {key} needs a -v or -c, only the first -k may go without")),
+            (Some(_), Some(_)) => return Args::BadArgs(String::from("-v and -c cannot be combined")),
+            _ => opts.predicates.push(Predicate { key, val, contains, negated }),
+        }
+    }
+    let zip = string("zip");
+    let entry = string("entry");
+    let files_from = string("files-from");
+    let pattern = string("regex");
+    if let Some(expr) = string("query") {
+        match parse_expr(&expr) {
+            Ok(expr) => opts.expr = Some(expr),
+            Err(e) => return Args::BadArgs(format!("invalid expression for --query: {e}")),
+        }
+    }
+    let explicit_stdin = match string("file") {
+        Some(file) if file == "-" => true,
+        Some(file) => {
+            input = Input::File(file);
+            false
+        },
+        None => false,
+    };
+
+    opts.error_format = match string("error-format").as_deref() {
+        Some("json") => ErrorFormat::Json,
+        _ => ErrorFormat::Text,
+    };
+    opts.color = match string("color").as_deref() {
+        Some("always") => Some(Color::Always),
+        Some("never") => Some(Color::Never),
+        Some(_) => Some(Color::Auto),
+        None => None,
+    };
+    opts.schema = match string("schema").as_deref() {
+        Some("vivaldi5") => Some(Schema::Vivaldi5),
+        Some(_) => Some(Schema::Vivaldi6),
+        None => None,
+    };
+    opts.ignore_case = flag("ignore-case");
+    opts.combined_field = flag("combined-field");
+    opts.any_field = flag("any-field");
+    opts.word = flag("word");
+    opts.raw_match = flag("raw-match");
+    opts.normalize_whitespace = flag("normalize-whitespace");
+    opts.invert = flag("invert");
+    opts.print_key = string("print-key");
+    opts.output_fields = matches.get_many::<String>("output-field").map(|fields| fields.cloned().collect()).unwrap_or_default();
+    if opts.print_key.is_some() && !opts.output_fields.is_empty() {
+        return Args::BadArgs(String::from("--print-key and --output-field cannot be combined"));
+    }
+    opts.child_count = flag("child-count");
+    opts.children_only = flag("children-only");
+    opts.format_content = flag("format-content");
+    opts.max_content_bytes = number("max-content-bytes");
+    opts.depth_of = flag("depth-of");
+    opts.byte_offset = flag("byte-offset");
+    opts.show_path = flag("show-path");
+    opts.dump_path = flag("dump-path");
+    opts.index_path = flag("index-path");
+    opts.template = string("template").map(|template| unescape(&template));
+    opts.escape_newlines = flag("escape-newlines");
+    opts.numbered = flag("numbered");
+    opts.context = number("context");
+    opts.occurrences = flag("occurrences");
+    opts.indent_preview = flag("indent-preview");
+    opts.hash = flag("hash");
+    opts.group_by_folder = flag("group-by-folder");
+    opts.post_order = flag("post-order");
+    opts.nth = number("nth");
+    if opts.nth == Some(0) {
+        return Args::BadArgs(String::from("--nth counts from 1"));
+    }
+    opts.first = flag("first");
+    opts.last = flag("last");
+    opts.all = flag("all") || subcommand == Some("search");
+    opts.separator = string("separator").map(|separator| unescape(&separator));
+    opts.match_limit_per_file = number("match-limit-per-file");
+    opts.rank = flag("rank");
+    opts.sample = number("sample");
+    opts.seed = matches.get_one::<u64>("seed").copied();
+    opts.no_children_in_full = flag("no-children-in-full");
+    opts.exclude_keys = matches.get_many::<String>("exclude-key").map(|keys| keys.cloned().collect()).unwrap_or_default();
+    opts.json = flag("json");
+    opts.json_array = flag("json-array");
+    opts.opml = flag("opml");
+    opts.unique = flag("unique");
+    opts.output_dir = string("output-dir");
+    opts.has = string("has");
+    opts.min_value = matches.get_one::<f64>("min").copied();
+    opts.max_value = matches.get_one::<f64>("max").copied();
+    opts.min_len = number("min-len");
+    opts.max_len = number("max-len");
+    if let Some(since) = string("since") {
+        match parse_duration(&since) {
+            Some(duration) => opts.modified_since = Some(chrome_now() - duration),
+            None => return Args::BadArgs(format!("invalid duration '{since}' for --since, expected e.g. 7d, 2h or 30m")),
+        }
+    }
+    for (name, bound) in [("after", &mut opts.added_after), ("before", &mut opts.added_before)] {
+        if let Some(date) = string(name) {
+            match parse_iso_date(&date) {
+                Some(timestamp) => *bound = Some(timestamp),
+                None => return Args::BadArgs(format!("invalid date '{date}' for --{name}, expected e.g. 2024-01-31 or 2024-01-31T08:30")),
+            }
+        }
+    }
+    opts.max_depth = number("depth");
+    opts.summary_fields = string("fields").map(|fields| {
+        fields.split(',').map(str::trim).filter(|field| !field.is_empty()).map(String::from).collect()
+    });
+    opts.head = number("head");
+    opts.tail = number("tail");
+    opts.format = flag("yaml").then_some(OutputFormat::Yaml);
+    // --truncate sets both, the specific flags take precedence over it
+    opts.truncate_subject = number("truncate-subject").or(number("truncate"));
+    opts.truncate_content = number("truncate-content").or(number("truncate"));
+    opts.compact_empty = flag("compact-empty");
+    opts.root_path = string("root-path");
+    opts.root_folder = string("root");
+    opts.skip_folders = matches.get_many::<String>("skip-folder").map(|folders| folders.cloned().collect()).unwrap_or_default();
+    opts.note_path = string("path");
+    opts.pointer = string("pointer");
+    opts.normalize = flag("normalize");
+    opts.branching = flag("branching");
+    opts.extract_attachments = string("extract-attachments");
+    opts.flatten = flag("flatten");
+    opts.with_path = flag("with-path");
+    opts.keys = flag("keys");
+    opts.strict_json = flag("strict-json");
+    opts.require_all_files = flag("require-all-files");
+    opts.keep_going = flag("ignore-missing-file");
+    opts.folder_counts = flag("folder-counts");
+    opts.top_longest = number("top-longest");
+    if let Some(replace) = matches.get_many::<String>("replace") {
+        if let [from, to] = replace.collect::<Vec<_>>()[..] {
+            opts.replace = Some((from.clone(), to.clone()));
+        }
+    }
+    opts.output = string("output");
+    opts.dry_run = flag("dry-run");
+    opts.merge = string("merge");
+    opts.serve = string("serve");
+    opts.repl = flag("repl");
+    opts.verbose = flag("verbose");
+    // the two override each other, so only the last one given is set
+    if flag("pager") {
+        opts.pager = Pager::Always;
+    } else if flag("no-pager") {
+        opts.pager = Pager::Never;
+    }
+    opts.timing = flag("timing");
+    opts.progress = flag("progress");
+
+    let has_query = key.is_some() || opts.expr.is_some() || opts.any_field || opts.combined_field || opts.filters_alone() || opts.pointer.is_some() || opts.note_path.is_some();
+    if opts.invert && !has_query {
+        return Args::BadArgs(String::from("--invert needs a query or a filter to invert, e.g. --invert -k subject -c Todo"));
+    }
+    match subcommand {
+        Some(name @ ("get" | "search")) if !has_query => {
+            return Args::BadArgs(format!("{name} needs a query, e.g. {name} -k subject -c Todo"));
+        },
+        Some(name @ ("summary" | "export")) if has_query => {
+            return Args::BadArgs(format!("{name} prints the whole tree and cannot be combined with a query"));
+        },
+        Some("export") => match matches.get_one::<String>("format").map(String::as_str) {
+            Some("opml") => opts.opml = true,
+            Some("flat") => opts.flatten = true,
+            format => {
+                // the default json must not override the format of the config file
+                if matches.value_source("format") == Some(ValueSource::CommandLine) {
+                    let format = if format == Some("yaml") { OutputFormat::Yaml } else { OutputFormat::Json };
+                    opts.format = opts.format.or(Some(format));
+                }
+                opts.pointer = Some(String::new());
+            },
+        },
+        _ => {},
+    }
+
+    if let (Some(_v), Some(_c)) = (&val, &contains) {
+        return Args::BadArgs(String::from("-v and -c cannot be combined"));
+    }
+    if opts.note_path.is_some() && (key.is_some() || opts.any_field || opts.combined_field || opts.has.is_some() || opts.pointer.is_some()) {
+        return Args::BadArgs(String::from("--path cannot be combined with -k, --any-field, --combined-field, --has or --pointer"));
+    }
+    if opts.negated && key.is_none() && !opts.any_field && !opts.combined_field {
+        return Args::BadArgs(String::from("--not needs a -k, --any-field or --combined-field before it"));
+    }
+    if opts.expr.is_some() && (key.is_some() || val.is_some() || contains.is_some() || pattern.is_some() || opts.any_field || opts.combined_field || opts.has_range() || opts.note_path.is_some()) {
+        return Args::BadArgs(String::from("--query cannot be combined with -k, -v, -c, -r, --any-field, --combined-field, --min, --max or --path"));
+    }
+    if let Some(pattern) = pattern {
+        if val.is_some() || contains.is_some() {
+            return Args::BadArgs(String::from("-r cannot be combined with -v or -c"));
+        }
+        // compiled once all flags are known, as -i may come after it
+        match RegexBuilder::new(&pattern).case_insensitive(opts.ignore_case).build() {
+            Ok(regex) => opts.regex = Some(regex),
+            Err(e) => return Args::BadArgs(format!("invalid pattern for -r: {e}")),
+        }
+    }
+    match (zip, &input) {
+        (Some(archive), Input::Stdin) => input = Input::Zip { archive, entry },
+        // a zip archive cannot be read together with a plain file
+        (Some(_), _) => return Args::BadArgs(String::from("--zip cannot be combined with a file argument")),
+        (None, _) if entry.is_some() => return Args::BadArgs(String::from("--entry requires --zip")),
+        (None, _) => {},
+    }
+    match (files_from, &input) {
+        (Some(list), Input::Stdin) => input = Input::FilesFrom(list),
+        (Some(_), _) => return Args::BadArgs(String::from("--files-from cannot be combined with a file argument")),
+        (None, _) => {},
+    }
+    if let (Input::Stdin, false, Some(file)) = (&input, explicit_stdin, notes_file) {
+        if !file.is_empty() {
+            input = Input::File(file);
+        }
+    }
+    // merging and the REPL work on a single notes file
+    if cfg!(not(unix)) && opts.serve.is_some() {
+        return Args::BadArgs(String::from("--serve needs unix sockets, which this platform does not have"));
+    }
+    if let (true, Input::FilesFrom(_)) = (opts.repl || opts.serve.is_some() || opts.merge.is_some() || opts.replace.is_some(), &input) {
+        return Args::BadArgs(String::from("--repl, --serve, --merge and --replace need a single notes file"));
+    }
+    if (opts.output.is_some() || opts.dry_run) && opts.replace.is_none() {
+        return Args::BadArgs(String::from("--output and --dry-run require --replace"));
+    }
+    // without --output the edited notes are written back to the input file
+    if let (true, None, Input::Stdin | Input::Zip { .. }) = (opts.replace.is_some(), &opts.output, &input) {
+        return Args::BadArgs(String::from("--replace needs --output when the notes are not read from a file"));
+    }
+    if opts.replace.is_some() && key.is_none() && opts.expr.is_none() && !opts.any_field && !opts.combined_field && opts.has.is_none() {
+        return Args::BadArgs(String::from("--replace needs a query to choose the notes to edit, e.g. -k subject -c Todo"));
+    }
+    if (opts.rank || opts.unique || opts.output_dir.is_some() || opts.json_array || opts.match_limit_per_file.is_some() || opts.group_by_folder) && !opts.all {
+        return Args::BadArgs(String::from("--rank, --unique, --output-dir, --json-array, --match-limit-per-file and --group-by-folder require --all"));
+    }
+    if opts.require_all_files && opts.keep_going {
+        return Args::BadArgs(String::from("--require-all-files and --ignore-missing-file cannot be combined"));
+    }
+    if opts.with_path && !opts.flatten {
+        return Args::BadArgs(String::from("--with-path requires --flatten"));
+    }
+    if opts.occurrences && (!opts.all || contains.is_none()) {
+        return Args::BadArgs(String::from("--occurrences requires --all and -c"));
+    }
+    if opts.nth.is_some() && (opts.all || opts.sample.is_some() || opts.last || opts.first) {
+        return Args::BadArgs(String::from("--nth cannot be combined with --all, --sample, --last or --first"));
+    }
+    if opts.first && (opts.all || opts.sample.is_some() || opts.last) {
+        return Args::BadArgs(String::from("--first cannot be combined with --all, --sample or --last"));
+    }
+    if opts.last && (opts.all || opts.sample.is_some()) {
+        return Args::BadArgs(String::from("--last cannot be combined with --all or --sample"));
+    }
+    if opts.seed.is_some() && opts.sample.is_none() {
+        return Args::BadArgs(String::from("--seed requires --sample"));
+    }
+    // the queries are read from stdin, so the notes have to come from a file
+    if let (true, Input::Stdin) = (opts.repl, &input) {
+        return Args::BadArgs(String::from("--repl needs a notes file argument"));
+    }
+    // modes that define their own field replace -k, so they need -v or -c
+    // but must not be combined with -k
+    if opts.any_field && opts.combined_field {
+        return Args::BadArgs(String::from("--any-field and --combined-field cannot be combined"));
+    }
+    if opts.has_range() && (val.is_some() || contains.is_some() || !opts.predicates.is_empty()) {
+        return Args::BadArgs(String::from("--min and --max cannot be combined with -v, -c or a second -k"));
+    }
+    let has_field_mode = opts.any_field || opts.combined_field;
+    match (&key, val.is_some() || contains.is_some() || opts.has_range(), has_field_mode) {
+        (Some(_), _, true) | (None, false, true) => {
+            Args::BadArgs(String::from("--any-field and --combined-field need -v or -c and cannot be combined with -k"))
+        },
+        // handle case where key is empty but not others
+        (None, true, false) => Args::BadArgs(String::from("-v, -c, --min and --max need a -k field")),
+        _ => Args::Key { key, val, contains, input, opts },
+    }
+}
+
+/// The field of a note that the value or contents are matched against.
+enum Field {
+    /// The field chosen with -k.
+    Key(String),
+    /// Every string field of the note, chosen with --any-field.
+    Any,
+    /// The subject and the content as one text, chosen with --combined-field.
+    Combined,
+    /// The expression of --query, which is kept in the options.
+    Expr,
+    /// No field at all, the note only has to pass the filters, e.g. --has.
+    Unset,
+}
+
+impl Options {
+    /// Whether structured output is printed as YAML.
+    fn yaml(&self) -> bool {
+        self.format == Some(OutputFormat::Yaml)
+    }
+
+    /// Whether folders are matched too, rather than only the notes in them,
+    /// because the output describes their children.
+    fn matches_folders(&self) -> bool {
+        self.child_count || self.children_only || self.opml
+    }
+
+    /// The children of a folder with their positions, without the folders
+    /// excluded with --skip-folder.
+    fn visited_children<'s, 'a: 's>(&'s self, children: &'a [Value]) -> impl Iterator<Item = (usize, &'a Value)> + 's {
+        children.iter().enumerate().filter(move |(_, child)| !self.skips(child))
+    }
+
+    /// Whether the note is a folder excluded with --skip-folder.
+    fn skips(&self, json: &Value) -> bool {
+        self.skip_folders.iter().any(|name| is_folder_named(json, name))
+    }
+
+    /// `skips` for a folder of the model.
+    fn skips_folder(&self, folder: &Folder) -> bool {
+        self.skip_folders.iter().any(|name| folder.subject.as_deref() == Some(name) || folder.id.as_deref() == Some(name))
+    }
+
+    /// Whether the date_added field of a note is within --after and
+    /// --before, if given.
+    fn added_in_range(&self, date_added: &Value) -> bool {
+        if self.added_after.is_none() && self.added_before.is_none() {
+            return true;
+        }
+        timestamp(date_added).is_some_and(|added| {
+            self.added_after.is_none_or(|after| added >= after) && self.added_before.is_none_or(|before| added < before)
+        })
+    }
+
+    /// Whether --has, --since, --min-len or --max-len are given, which select
+    /// the notes passing them even without a -k.
+    fn filters_alone(&self) -> bool {
+        self.has.is_some() || self.modified_since.is_some() || self.min_len.is_some() || self.max_len.is_some()
+    }
+
+    /// Whether -k selects a number field that has to be within --min and --max.
+    fn has_range(&self) -> bool {
+        self.min_value.is_some() || self.max_value.is_some()
+    }
+}
+
+/// A further field of the note and the exact value "val" or the contents
+/// "contains" it must have, from repeated -k, or must not have when negated.
+#[derive(Debug, PartialEq)]
+struct Predicate {
+    key: String,
+    val: Option<String>,
+    contains: Option<String>,
+    negated: bool,
+}
+
+impl Predicate {
+    fn matches(&self, opts: &Options, json: &Value) -> bool {
+        value_matches(&json[&self.key], &self.val, &self.contains, opts) != self.negated
+    }
+
+    /// Why the note does not satisfy the predicate.
+    fn mismatch_reason(&self, opts: &Options, json: &Value) -> String {
+        if self.negated {
+            format!("field '{}' matches, excluded by --not", self.key)
+        } else {
+            key_mismatch(&self.key, &self.val, &self.contains, opts, json)
+        }
+    }
+}
+
+/// A boolean expression over the fields of a note, from --query.
+#[derive(Debug, PartialEq)]
+enum Expr {
+    /// `key==value` or `key~contents`, or their negations `!=` and `!~`.
+    Field(Predicate),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// What a search looks for: a field of the note and either the exact value
+/// "val" or the contents "contains" it must have.
+struct Query {
+    field: Field,
+    val: Option<String>,
+    contains: Option<String>,
+}
+
+impl Query {
+    /// The search to run, or `None` when no search was requested and the
+    /// summary should be printed instead.
+    fn from_args(key: Option<String>, val: Option<String>, contains: Option<String>, opts: &Options) -> Option<Query> {
+        let field = match key {
+            _ if opts.any_field => Field::Any,
+            _ if opts.combined_field => Field::Combined,
+            Some(key) => Field::Key(key),
+            None if opts.expr.is_some() => Field::Expr,
+            None if opts.filters_alone() => Field::Unset,
+            None => return None,
+        };
+        Some(Query { field, val, contains })
+    }
+
+    /// The text searched for, for scoring and counting matches.
+    fn text(&self) -> &str {
+        self.contains.as_deref().or(self.val.as_deref()).unwrap_or_default()
+    }
+}
+
+/// A matched note, along with the positions in the children arrays that lead
+/// to it from the root.
+struct Match<'a> {
+    note: &'a Value,
+    path: Vec<usize>,
+}
+
+/// Microseconds between the Chrome epoch (1601-01-01), which Vivaldi uses for
+/// its timestamps, and the Unix epoch.
+const CHROME_EPOCH_OFFSET_MICROS: i64 = 11_644_473_600 * 1_000_000;
+
+/// Current time as a Chrome epoch timestamp.
+fn chrome_now() -> i64 {
+    let since_unix = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_unix.as_micros() as i64 + CHROME_EPOCH_OFFSET_MICROS
+}
+
+/// Parse an ISO 8601 date, e.g. "2024-01-31", or a date and time in UTC, e.g.
+/// "2024-01-31T08:30" or "2024-01-31T08:30:00Z". Returns it as a Chrome epoch
+/// timestamp.
+fn parse_iso_date(date: &str) -> Option<i64> {
+    let date = date.strip_suffix('Z').unwrap_or(date);
+    let (day, time) = match date.split_once(['T', ' ']) {
+        Some((day, time)) => (day, Some(time)),
+        None => (date, None),
+    };
+    let fields: Vec<&str> = day.split('-').collect();
+    let [year, month, day] = fields[..] else {
+        return None;
+    };
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    let (year, month, day): (i64, u32, u32) = (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let seconds = match time {
+        None => 0,
+        Some(time) => {
+            let fields: Vec<&str> = time.split(':').collect();
+            let [hours, minutes, seconds] = match fields[..] {
+                [hours, minutes] => [hours, minutes, "00"],
+                [hours, minutes, seconds] => [hours, minutes, seconds],
+                _ => return None,
+            };
+            let (hours, minutes, seconds): (u32, u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?, seconds.parse().ok()?);
+            if hours > 23 || minutes > 59 || seconds > 59 {
+                return None;
+            }
+            i64::from(hours * 3600 + minutes * 60 + seconds)
+        },
+    };
+    let unix_seconds = days_from_civil(year, month, day) * 24 * 60 * 60 + seconds;
+    Some(unix_seconds * 1_000_000 + CHROME_EPOCH_OFFSET_MICROS)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days from the Unix epoch to the date in the proleptic Gregorian calendar,
+/// negative before 1970.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    // years start in March, so that the leap day comes last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse a relative duration made of one or more `<number><unit>` groups,
+/// where the unit is one of `d`, `h` or `m`, e.g. "7d" or "1d12h". Returns the
+/// duration in microseconds.
+fn parse_duration(duration: &str) -> Option<i64> {
+    let mut total: i64 = 0;
+    let mut number = String::new();
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit: i64 = match c {
+            'd' => 24 * 60 * 60 * 1_000_000,
+            'h' => 60 * 60 * 1_000_000,
+            'm' => 60 * 1_000_000,
+            _ => return None,
+        };
+        let n: i64 = number.parse().ok()?;
+        total = total.checked_add(n.checked_mul(unit)?)?;
+        number.clear();
+    }
+    if !number.is_empty() || total == 0 {
+        return None;
+    }
+    Some(total)
+}
+
+/// Read a Chrome epoch timestamp field, which Vivaldi stores as a string but
+/// which may also appear as a plain number.
+fn chrome_timestamp(json: &Value, field: &str) -> Option<i64> {
+    timestamp(&json[field])
+}
+
+fn timestamp(value: &Value) -> Option<i64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        Value::Number(n) => n.as_i64(),
+        _ => None,
+    }
+}
+
+/// When a note was last modified. Notes that were never modified only carry
+/// their creation date.
+fn note_modified(json: &Value) -> Option<i64> {
+    chrome_timestamp(json, "date_modified").or_else(|| chrome_timestamp(json, "date_added"))
+}
+
+/// Check the filters that apply on top of the key match.
+fn passes_filters(opts: &Options, json: &Value) -> bool {
+    failed_filter(opts, json).is_none()
+}
+
+/// The reason why the note is left out by the filters, if it is.
+fn failed_filter(opts: &Options, json: &Value) -> Option<String> {
+    if let Some(since) = opts.modified_since {
+        if note_modified(json).is_none_or(|m| m < since) {
+            return Some(String::from("not modified within --since"));
+        }
+    }
+    if !opts.added_in_range(&json["date_added"]) {
+        return Some(String::from("not added between --after and --before"));
+    }
+    if let Some(field) = &opts.has {
+        if json[field].is_null() {
+            return Some(format!("field '{field}' for --has is missing"));
+        }
+    }
+    if opts.min_len.is_some() || opts.max_len.is_some() {
+        let len = note_content(json).chars().count();
+        if opts.min_len.is_some_and(|min| len < min) || opts.max_len.is_some_and(|max| len > max) {
+            return Some(format!("content length {len} is out of range"));
+        }
+    }
+    None
+}
+
+/// Counts the nodes visited by a traversal and, when enabled, periodically
+/// reports the count on stderr so that long runs do not look hung.
+struct Progress {
+    enabled: bool,
+    visited: Cell<usize>,
+}
+
+impl Progress {
+    /// Number of visited nodes between two progress reports.
+    const INTERVAL: usize = 1000;
+
+    /// Only report progress when asked to and when stderr is a terminal, so
+    /// that redirected stderr is not filled with carriage returns.
+    fn new(requested: bool) -> Progress {
+        Progress {
+            enabled: requested && io::stderr().is_terminal(),
+            visited: Cell::new(0),
+        }
+    }
+
+    fn tick(&self) {
+        let visited = self.visited.get() + 1;
+        self.visited.set(visited);
+        if self.enabled && visited.is_multiple_of(Progress::INTERVAL) {
+            eprint!("\rvisited {visited} notes");
+        }
+    }
+
+    fn finish(&self) {
+        if self.enabled {
+            eprintln!("\rvisited {} notes", self.visited.get());
+        }
+    }
+}
+
+/// Call `f` on every note under `json`, and on the folders too when the output
+/// describes them, with the child positions leading to it. A folder comes
+/// before everything in it, or after it with --post-order, and folders
+/// excluded with --skip-folder are left out. The walk stops at the first
+/// `Break`, which is returned.
+fn visit_notes<'a, B, F>(opts: &Options, progress: &Progress, json: &'a Value, path: &mut Vec<usize>, f: &mut F) -> ControlFlow<B>
+where
+    F: FnMut(&'a Value, &[usize]) -> ControlFlow<B>
+{
+    progress.tick();
+    let Some(children) = note_children(json) else {
+        return f(json, path);
+    };
+    if opts.matches_folders() && !opts.post_order {
+        f(json, path)?;
+    }
+    for (i, child) in opts.visited_children(children) {
+        path.push(i);
+        let res = visit_notes(opts, progress, child, path, f);
+        path.pop();
+        res?;
+    }
+    if opts.matches_folders() && opts.post_order {
+        f(json, path)?;
+    }
+    ControlFlow::Continue(())
+}
+
+/// Traverse the notes json representation and retrieve the first note object
+/// that has a field "key" with the value "val".
+fn traverse_json<'a>(
+    query: &Query,
+    opts: &Options,
+    progress: &Progress,
+    json: &'a Value,
+    path: &mut Vec<usize>
+) -> Option<Match<'a>> {
+    let found = visit_notes(opts, progress, json, path, &mut |note, path| match check_note(query, opts, note, path) {
+        true => ControlFlow::Break(Match { note, path: path.to_vec() }),
+        false => ControlFlow::Continue(()),
+    });
+    found.break_value()
+}
+
+/// Like `traverse_json`, but skip matches until `remaining` of them have been
+/// seen and return that last one.
+fn traverse_json_nth<'a>(
+    query: &Query,
+    opts: &Options,
+    progress: &Progress,
+    json: &'a Value,
+    path: &mut Vec<usize>,
+    remaining: &mut usize
+) -> Option<Match<'a>> {
+    let found = visit_notes(opts, progress, json, path, &mut |note, path| {
+        if check_note(query, opts, note, path) {
+            *remaining -= 1;
+            if *remaining == 0 {
+                return ControlFlow::Break(Match { note, path: path.to_vec() });
+            }
+        }
+        ControlFlow::Continue(())
+    });
+    found.break_value()
+}
+
+/// Like `traverse_json`, but walk the whole tree and return the last match in
+/// pre-order instead of stopping at the first one.
+fn traverse_json_last<'a>(
+    query: &Query,
+    opts: &Options,
+    progress: &Progress,
+    json: &'a Value,
+    path: &mut Vec<usize>
+) -> Option<Match<'a>> {
+    let mut last = None;
+    let _: ControlFlow<()> = visit_notes(opts, progress, json, path, &mut |note, path| {
+        if check_note(query, opts, note, path) {
+            last = Some(Match { note, path: path.to_vec() });
+        }
+        ControlFlow::Continue(())
+    });
+    last
+}
+
+/// Traverse the notes json representation and collect every note object that
+/// matches, in the same order in which `traverse_json` would find them.
+fn traverse_json_all<'a>(
+    query: &Query,
+    opts: &Options,
+    progress: &Progress,
+    json: &'a Value,
+    path: &mut Vec<usize>,
+    results: &mut Vec<Match<'a>>
+) {
+    let _: ControlFlow<()> = visit_notes(opts, progress, json, path, &mut |note, path| {
+        if check_note(query, opts, note, path) {
+            results.push(Match { note, path: path.to_vec() });
+        }
+        ControlFlow::Continue(())
+    });
+}
+
+/// The children of a folder, or `None` for notes and empty folders. A file
+/// whose top level is an array of note trees is treated as a folder holding
+/// each of those roots.
+fn note_children(json: &Value) -> Option<&Vec<Value>> {
+    match json {
+        Value::Array(roots) if !roots.is_empty() => Some(roots),
+        _ => match &json["children"] {
+            Value::Array(children) if !children.is_empty() => Some(children),
+            _ => None,
+        },
+    }
+}
+
+/// The content of a note, empty for folders and notes without content.
+fn note_content(json: &Value) -> String {
+    match &json["content"] {
+        Value::String(content) => String::from(content),
+        _ => String::new(),
+    }
+}
+
+/// What is printed for a match: the positions leading to it with --index-path,
+/// or else what `note_output` prints for the note.
+fn match_output(opts: &Options, m: &Match) -> Option<String> {
+    if opts.index_path {
+        let positions: Vec<String> = m.path.iter().map(usize::to_string).collect();
+        return Some(positions.join("/"));
+    }
+    if let Some(template) = &opts.template {
+        return Some(fill_template(template, m.note));
+    }
+    note_output(opts, m.note)
+}
+
+/// The JSON Pointer to the note at the child positions of `path`, made of
+/// indices only so that no keys need to be escaped.
+fn json_pointer(root: &Value, path: &[usize]) -> String {
+    let mut pointer = String::new();
+    for (depth, i) in path.iter().enumerate() {
+        // the roots of an array file are not under a children key
+        if depth > 0 || !root.is_array() {
+            pointer.push_str("/children");
+        }
+        pointer.push_str(&format!("/{i}"));
+    }
+    pointer
+}
+
+/// The note reached by following the child positions of `path` from `root`.
+fn note_at<'a>(root: &'a Value, path: &[usize]) -> Option<&'a Value> {
+    path.iter().try_fold(root, |note, &i| note_children(note)?.get(i))
+}
+
+/// A short name for a note in listings: its subject, or else its id, or else
+/// the start of its content.
+fn note_label(json: &Value) -> String {
+    match (json["subject"].as_str(), json["id"].as_str()) {
+        (Some(subject), _) if !subject.is_empty() => String::from(subject),
+        (_, Some(id)) if !id.is_empty() => String::from(id),
+        _ => match json["content"].as_str() {
+            Some(content) if !content.is_empty() => truncate(content, 30).to_string(),
+            _ => String::from("(untitled)"),
+        },
+    }
+}
+
+/// The subjects of the folders leading from the root to the note at `path`,
+/// and the note's own label, joined by `/`. Folders without a subject are
+/// `<untitled>`, as in --flatten --with-path.
+fn breadcrumbs(root: &Value, path: &[usize]) -> String {
+    // the roots of an array file are trees, not folders the user made
+    let skipped = if root.is_array() { 2 } else { 1 };
+    let mut names: Vec<String> = (skipped..path.len())
+        .filter_map(|depth| note_at(root, &path[..depth]))
+        .map(folder_name)
+        .collect();
+    if let Some(note) = note_at(root, path).filter(|_| !path.is_empty()) {
+        names.push(note_label(note));
+    }
+    names.join("/")
+}
+
+/// The name of a folder in paths.
+fn folder_name(json: &Value) -> String {
+    match json["subject"].as_str() {
+        Some(subject) if !subject.is_empty() => String::from(subject),
+        _ => String::from("<untitled>"),
+    }
+}
+
+/// The output for a match followed, with --context, by the subjects of the
+/// notes around it in its folder. The match itself is marked with `>`.
+fn render_match(opts: &Options, root: &Value, m: &Match) -> Option<String> {
+    let output = if opts.dump_path {
+        let pointer = format!("{}{}", opts.root_path.as_deref().unwrap_or_default(), json_pointer(root, &m.path));
+        format!("{pointer}\t{}", escape(&note_label(m.note)))
+    } else if opts.depth_of {
+        // the roots of an array file are trees, not folders the user made
+        let roots = usize::from(root.is_array());
+        (m.path.len() - roots).to_string()
+    } else {
+        match_output(opts, m)?
+    };
+    let output = if opts.show_path { format!("{}\t{output}", breadcrumbs(root, &m.path)) } else { output };
+    let output = if opts.escape_newlines { escape(&output) } else { output };
+    let (Some(n), Some((&index, parent_path))) = (opts.context, m.path.split_last()) else {
+        return Some(output);
+    };
+    let Some(siblings) = note_at(root, parent_path).and_then(note_children) else {
+        return Some(output);
+    };
+    let mut res = output;
+    res.push_str("\n--- context");
+    for (i, sibling) in siblings.iter().enumerate().take(index + n + 1).skip(index.saturating_sub(n)) {
+        let marker = if i == index { '>' } else { ' ' };
+        res.push_str(&format!("\n{marker} {}", note_label(sibling)));
+    }
+    Some(res)
+}
+
+/// Prefix every line with its right-aligned line number, like `cat -n`.
+fn number_lines(s: &str) -> String {
+    let lines: Vec<String> = s.split('\n')
+        .enumerate()
+        .map(|(i, line)| format!("{:>6}\t{line}", i + 1))
+        .collect();
+    lines.join("\n")
+}
+
+/// Replace each `{field}` in the template with that field of the note, or
+/// with nothing if the note does not have it.
+fn fill_template(template: &str, json: &Value) -> String {
+    let mut res = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        res.push_str(&rest[..start]);
+        match &json[&rest[start + 1..start + len]] {
+            Value::Null => {},
+            Value::String(s) => res.push_str(s),
+            value => res.push_str(&value.to_string()),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    res.push_str(rest);
+    res
+}
+
+/// What is printed for a matched note: its content, its number of children or
+/// their labels, the whole note, or the field chosen with --print-key. Nothing
+/// is printed if that field is missing.
+fn note_output(opts: &Options, json: &Value) -> Option<String> {
+    if opts.child_count {
+        return Some(json["children"].as_array().map_or(0, Vec::len).to_string());
+    }
+    if !opts.output_fields.is_empty() {
+        return Some(fields_output(opts, json));
+    }
+    if opts.json {
+        return serialize(opts, &without_excluded(opts, json));
+    }
+    if opts.children_only {
+        let labels: Vec<String> = json["children"].as_array().into_iter().flatten().map(note_label).collect();
+        return Some(labels.join("\n"));
+    }
+    let Some(print_key) = &opts.print_key else {
+        let content = note_content(json);
+        let content = if opts.format_content { format_json_content(content) } else { content };
+        return Some(match opts.max_content_bytes {
+            Some(max) => truncate_bytes(content, max),
+            None => content,
+        });
+    };
+    match &json[print_key] {
+        Value::Null => None,
+        Value::String(s) => Some(String::from(s)),
+        value => Some(value.to_string()),
+    }
+}
+
+/// The fields of --output-field, as a json object with --json or else as one
+/// line of tab separated values, where a missing field is left empty.
+fn fields_output(opts: &Options, json: &Value) -> String {
+    if opts.json {
+        let fields: Map<String, Value> = opts.output_fields.iter().map(|field| (field.clone(), json[field].clone())).collect();
+        return serialize(opts, &Value::Object(fields)).unwrap_or_default();
+    }
+    let values: Vec<String> = opts.output_fields.iter().map(|field| match &json[field] {
+        Value::Null => String::new(),
+        // escaped so that every match stays on one line
+        Value::String(s) => escape(s),
+        value => value.to_string(),
+    }).collect();
+    values.join("\t")
+}
+
+/// Pretty-print content that holds a json object or array, and keep any
+/// other content as it is.
+fn format_json_content(content: String) -> String {
+    match serde_json::from_str::<Value>(&content) {
+        Ok(value @ (Value::Object(_) | Value::Array(_))) => serde_json::to_string_pretty(&value).unwrap_or(content),
+        _ => content,
+    }
+}
+
+/// Check whether the chosen field of a single note matches either the value
+/// "val" exactly or the contents "contains" as a substring.
+fn note_matches(query: &Query, opts: &Options, json: &Value) -> bool {
+    // --invert negates the whole query, filters included
+    (field_matches(query, opts, json) && passes_filters(opts, json)) != opts.invert
+}
+
+/// Whether the fields of the query have the values or contents searched for.
+fn field_matches(query: &Query, opts: &Options, json: &Value) -> bool {
+    (primary_matches(query, opts, json) != opts.negated) && opts.predicates.iter().all(|p| p.matches(opts, json))
+}
+
+/// Whether the field chosen by the first -k, or the field mode, matches.
+fn primary_matches(query: &Query, opts: &Options, json: &Value) -> bool {
+    let Query { field, val, contains } = query;
+    match field {
+        Field::Key(key) => value_matches(&json[key], val, contains, opts),
+        Field::Any => match json {
+            Value::Object(note) => note.values().any(|v| value_matches(v, val, contains, opts)),
+            _ => false,
+        },
+        Field::Combined => {
+            let combined = format!("{}\n{}", json["subject"].as_str().unwrap_or_default(), note_content(json));
+            value_matches(&Value::String(combined), val, contains, opts)
+        },
+        Field::Expr => opts.expr.as_ref().is_some_and(|expr| expr_matches(expr, opts, json)),
+        Field::Unset => true,
+    }
+}
+
+/// `note_matches`, tracing the decision and the reason for it on stderr with
+/// --verbose.
+fn check_note(query: &Query, opts: &Options, json: &Value, path: &[usize]) -> bool {
+    let matched = note_matches(query, opts, json);
+    if opts.verbose {
+        let positions: Vec<String> = path.iter().map(usize::to_string).collect();
+        let decision = if matched { String::from("matched") } else { format!("no match, {}", mismatch_reason(query, opts, json)) };
+        eprintln!("trace: /{} {:?}: {decision}", positions.join("/"), note_label(json));
+    }
+    matched
+}
+
+/// Why a note that `note_matches` rejected did not match.
+fn mismatch_reason(query: &Query, opts: &Options, json: &Value) -> String {
+    if field_matches(query, opts, json) {
+        return failed_filter(opts, json).unwrap_or_else(|| String::from("excluded by --invert"));
+    }
+    if primary_matches(query, opts, json) != opts.negated {
+        return opts.predicates.iter()
+            .find(|p| !p.matches(opts, json))
+            .map(|p| p.mismatch_reason(opts, json))
+            .unwrap_or_default();
+    }
+    match &query.field {
+        _ if opts.negated => String::from("the field matches, excluded by --not"),
+        Field::Key(key) => key_mismatch(key, &query.val, &query.contains, opts, json),
+        Field::Combined => String::from("the combined subject and content do not match"),
+        Field::Expr => String::from("the --query expression does not hold"),
+        _ => String::from("no field matches"),
+    }
+}
+
+/// Why the field `key` does not have the value or contents searched for.
+fn key_mismatch(key: &str, val: &Option<String>, contains: &Option<String>, opts: &Options, json: &Value) -> String {
+    match &json[key] {
+        Value::Null => format!("field '{key}' is missing"),
+        Value::Number(_) if opts.has_range() => format!("field '{key}' is out of range"),
+        _ if opts.has_range() => format!("field '{key}' is not a number"),
+        Value::String(_) if val.is_some() => format!("field '{key}' has a different value"),
+        Value::String(_) if contains.is_some() => format!("field '{key}' does not contain the text"),
+        Value::String(_) => format!("field '{key}' does not match the pattern"),
+        _ => format!("field '{key}' is not a string"),
+    }
+}
+
+fn value_matches(value: &Value, val: &Option<String>, contains: &Option<String>, opts: &Options) -> bool {
+    if opts.has_range() {
+        return matches!(value, Value::Number(n) if n.as_f64().is_some_and(|n| {
+            opts.min_value.is_none_or(|min| n >= min) && opts.max_value.is_none_or(|max| n <= max)
+        }));
+    }
+    let escaped;
+    let text = match value {
+        Value::String(k) if opts.raw_match => {
+            escaped = raw_json_string(k);
+            escaped.as_str()
+        },
+        Value::String(k) => k.as_str(),
+        _ => return false,
+    };
+    let text = matching_form(opts, text);
+    match (val, contains) {
+        (Some(v), None) => text == matching_form(opts, v),
+        (None, Some(c)) if opts.word => contains_word(&text, &matching_form(opts, c)),
+        (None, Some(c)) => text.contains(&*matching_form(opts, c)),
+        (None, None) => opts.regex.as_ref().is_some_and(|regex| regex.is_match(&text)),
+        _ => false,
+    }
+}
+
+/// The text as it is compared: with --normalize-whitespace every run of
+/// whitespace becomes a single space and with --ignore-case it is lowercased.
+/// Lowercasing follows Unicode, which folds case for nearly all letters but,
+/// unlike full case folding, leaves e.g. `ß` and `SS` different.
+fn matching_form<'a>(opts: &Options, text: &'a str) -> Cow<'a, str> {
+    let text = normalized_whitespace(opts, text);
+    if opts.ignore_case {
+        Cow::Owned(text.to_lowercase())
+    } else {
+        text
+    }
+}
+
+fn normalized_whitespace<'a>(opts: &Options, text: &'a str) -> Cow<'a, str> {
+    if !opts.normalize_whitespace {
+        return Cow::Borrowed(text);
+    }
+    let mut res = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        // char::is_whitespace includes the non-breaking space
+        if c.is_whitespace() {
+            if !in_space {
+                res.push(' ');
+            }
+            in_space = true;
+        } else {
+            res.push(c);
+            in_space = false;
+        }
+    }
+    Cow::Owned(res)
+}
+
+/// A string escaped as json, without the surrounding quotes. serde_json does
+/// not keep the escapes of the notes file, so this is how it writes the value
+/// back: quotes, backslashes and control characters are escaped, other
+/// characters are not, even when the file has them as `\u` escapes.
+fn raw_json_string(s: &str) -> String {
+    let quoted = Value::String(String::from(s)).to_string();
+    String::from(&quoted[1..quoted.len() - 1])
+}
+
+/// Check whether `word` occurs in `text` with no word character directly
+/// before or after it.
+fn contains_word(text: &str, word: &str) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+/// A word of a --query expression.
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// A field name or a bare value.
+    Word(String),
+    /// A value in double quotes, which may contain `\"` and `\\`.
+    Text(String),
+    Symbol(&'static str),
+}
+
+/// Split a --query expression into its words.
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    const SYMBOLS: [&str; 9] = ["==", "!=", "!~", "&&", "||", "~", "!", "(", ")"];
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if c == '"' {
+            let mut text = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i + 2,
+                    Some((_, '\\')) => text.extend(chars.next().map(|(_, c)| c)),
+                    Some((_, c)) => text.push(c),
+                    None => return Err(String::from("unterminated quote")),
+                }
+            };
+            tokens.push(Token::Text(text));
+            rest = &rest[end..];
+        } else {
+            let end = rest.find(|c: char| c.is_whitespace() || "=!~&|()\"".contains(c)).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected '{c}'"));
+            }
+            tokens.push(Token::Word(String::from(&rest[..end])));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse a --query expression. `!`/`not` binds tighter than `&&`/`and`,
+/// which binds tighter than `||`/`or`.
+fn parse_expr(expr: &str) -> Result<Expr, String> {
+    let tokens = tokenize(expr)?;
+    let mut pos = 0;
+    let parsed = parse_or(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(parsed),
+        Some(token) => Err(format!("unexpected {}", describe_token(token))),
+    }
+}
+
+/// Whether the token is the symbol or the keyword of an operator, keywords
+/// are matched ignoring case.
+fn is_operator(token: Option<&Token>, symbol: &str, keyword: &str) -> bool {
+    match token {
+        Some(Token::Symbol(s)) => *s == symbol,
+        Some(Token::Word(word)) => word.eq_ignore_ascii_case(keyword),
+        _ => false,
+    }
+}
+
+fn describe_token(token: &Token) -> String {
+    match token {
+        Token::Word(word) => format!("'{word}'"),
+        Token::Text(text) => format!("\"{text}\""),
+        Token::Symbol(symbol) => format!("'{symbol}'"),
+    }
+}
+
+fn parse_or(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_and(tokens, pos)?;
+    while is_operator(tokens.get(*pos), "||", "or") {
+        *pos += 1;
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_and(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    let mut expr = parse_unary(tokens, pos)?;
+    while is_operator(tokens.get(*pos), "&&", "and") {
+        *pos += 1;
+        expr = Expr::And(Box::new(expr), Box::new(parse_unary(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_unary(tokens: &[Token], pos: &mut usize) -> Result<Expr, String> {
+    if is_operator(tokens.get(*pos), "!", "not") {
+        *pos += 1;
+        return Ok(Expr::Not(Box::new(parse_unary(tokens, pos)?)));
+    }
+    match tokens.get(*pos) {
+        Some(Token::Symbol("(")) => {
+            *pos += 1;
+            let expr = parse_or(tokens, pos)?;
+            match tokens.get(*pos) {
+                Some(Token::Symbol(")")) => {
+                    *pos += 1;
+                    Ok(expr)
+                },
+                _ => Err(String::from("missing ')'")),
+            }
+        },
+        Some(Token::Word(key)) => {
+            let (negated, equals) = match tokens.get(*pos + 1) {
+                Some(Token::Symbol("==")) => (false, true),
+                Some(Token::Symbol("!=")) => (true, true),
+                Some(Token::Symbol("~")) => (false, false),
+                Some(Token::Symbol("!~")) => (true, false),
+                _ => return Err(format!("expected ==, !=, ~ or !~ after '{key}'")),
+            };
+            let text = match tokens.get(*pos + 2) {
+                Some(Token::Word(text) | Token::Text(text)) => text.clone(),
+                _ => return Err(format!("expected a value to compare '{key}' with")),
+            };
+            *pos += 3;
+            let (val, contains) = if equals { (Some(text), None) } else { (None, Some(text)) };
+            Ok(Expr::Field(Predicate { key: key.clone(), val, contains, negated }))
+        },
+        Some(token) => Err(format!("unexpected {}", describe_token(token))),
+        None => Err(String::from("unexpected end of the expression")),
+    }
+}
+
+/// Whether the note satisfies the expression. Fields are compared as with
+/// -v and -c, so that e.g. -i and --word apply.
+fn expr_matches(expr: &Expr, opts: &Options, json: &Value) -> bool {
+    match expr {
+        Expr::Field(p) => p.matches(opts, json),
+        Expr::Not(expr) => !expr_matches(expr, opts, json),
+        Expr::And(left, right) => expr_matches(left, opts, json) && expr_matches(right, opts, json),
+        Expr::Or(left, right) => expr_matches(left, opts, json) || expr_matches(right, opts, json),
+    }
+}
+
+/// The matched notes as the items of one array, so that all of them can be
+/// parsed at once.
+fn json_array(opts: &Options, matches: &[Match]) -> Vec<Value> {
+    matches.iter().map(|m| without_excluded(opts, m.note)).collect()
+}
+
+/// A copy of the note without the fields chosen with --exclude-key, and
+/// without its children with --no-children-in-full. The fields are copied
+/// one by one, so that a large subtree that is left out is never cloned.
+fn without_excluded(opts: &Options, json: &Value) -> Value {
+    let Value::Object(fields) = json else {
+        return json.clone();
+    };
+    let excluded = |key: &str| (opts.no_children_in_full && key == "children") || opts.exclude_keys.iter().any(|k| k == key);
+    Value::Object(fields.iter().filter(|(key, _)| !excluded(key)).map(|(k, v)| (k.clone(), v.clone())).collect())
+}
+
+/// Keep `n` of the matches chosen at random, in the order they were found.
+/// The same seed always picks the same matches.
+fn sample_matches(matches: Vec<Match>, n: usize, seed: Option<u64>) -> Vec<Match> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut picked = rand::seq::index::sample(&mut rng, matches.len(), n.min(matches.len())).into_vec();
+    picked.sort_unstable();
+    let mut picked = picked.into_iter().peekable();
+    matches.into_iter().enumerate()
+        .filter(|(i, _)| picked.next_if_eq(i).is_some())
+        .map(|(_, m)| m)
+        .collect()
+}
+
+/// Score how relevant a matched note is for the searched text: one point for
+/// every occurrence in its content and a bonus for every occurrence in its
+/// subject, so that subject matches rank above matches only in the content.
+/// The occurrences are counted the way they were matched, e.g. ignoring case.
+fn relevance(opts: &Options, json: &Value, text: &str) -> usize {
+    const SUBJECT_BONUS: usize = 10;
+    if text.is_empty() {
+        return 0;
+    }
+    let text = matching_form(opts, text);
+    let occurrences = |field: &str| json[field].as_str().map_or(0, |s| matching_form(opts, s).matches(&*text).count());
+    occurrences("content") + SUBJECT_BONUS * occurrences("subject")
+}
+
+/// Turn a subject into a file name: path separators and control characters
+/// are dropped and runs of whitespace become a single space.
+fn sanitize_file_name(name: &str) -> String {
+    let kept: String = name.chars()
+        .filter(|&c| c.is_whitespace() || (c != '/' && c != '\\' && !c.is_control()))
+        .collect();
+    kept.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Write each match to its own file in `dir`, named after the subject of the
+/// note or else its id. Names that are already taken get a counter appended.
+/// Returns the paths of the written files.
+fn write_matches(dir: &Path, opts: &Options, matches: &[Match]) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut taken = HashSet::new();
+    let mut written = Vec::new();
+    for m in matches {
+        let Some(output) = match_output(opts, m) else {
+            continue;
+        };
+        let base = ["subject", "id"].iter()
+            .filter_map(|field| m.note[field].as_str())
+            .map(sanitize_file_name)
+            .find(|name| !name.is_empty())
+            .unwrap_or_else(|| String::from("note"));
+        let path = dir.join(claim_name(&mut taken, &base, ".txt"));
+        fs::write(&path, output)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The file name `base` followed by `extension`, with a counter appended to
+/// the base when that name is already taken.
+fn claim_name(taken: &mut HashSet<String>, base: &str, extension: &str) -> String {
+    let mut name = format!("{base}{extension}");
+    let mut counter = 1;
+    while !taken.insert(name.clone()) {
+        counter += 1;
+        name = format!("{base} ({counter}){extension}");
+    }
+    name
+}
+
+/// Collect the attachments in the tree: objects of type attachment and the
+/// entries of attachments arrays.
+fn find_attachments<'a>(json: &'a Value, listed: bool, progress: &Progress, found: &mut Vec<&'a Value>) {
+    match json {
+        Value::Object(fields) => {
+            progress.tick();
+            if listed || json["type"] == "attachment" {
+                found.push(json);
+                return;
+            }
+            for (key, value) in fields {
+                find_attachments(value, key == "attachments", progress, found);
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|value| find_attachments(value, listed, progress, found)),
+        _ => (),
+    }
+}
+
+/// The embedded data of an attachment, as plain base64 or as a data URL, and
+/// the file extension that goes with the media type of a data URL. `None`
+/// when the attachment has no data at all.
+fn attachment_data(attachment: &Value) -> Option<Result<(Vec<u8>, String), base64::DecodeError>> {
+    let encoded = ["data", "base64", "content"].iter().find_map(|field| attachment[field].as_str())?;
+    let (extension, encoded) = match encoded.strip_prefix("data:").and_then(|url| url.split_once(";base64,")) {
+        Some((media_type, encoded)) => {
+            let subtype = media_type.split('/').nth(1).unwrap_or_default();
+            let subtype = subtype.split('+').next().unwrap_or_default();
+            (if subtype.is_empty() { String::new() } else { format!(".{subtype}") }, encoded)
+        },
+        None => (String::new(), encoded),
+    };
+    let encoded: String = encoded.chars().filter(|c| !c.is_whitespace()).collect();
+    Some(base64::engine::general_purpose::STANDARD.decode(encoded).map(|data| (data, extension)))
+}
+
+/// Write the embedded data of every attachment to its own file in `dir`,
+/// named after its file name, subject or id. Attachments that only store an
+/// id are reported as unresolved. Returns the paths of the written files.
+fn extract_attachments(dir: &Path, opts: &Options, json: &Value, progress: &Progress) -> io::Result<Vec<PathBuf>> {
+    let mut attachments = Vec::new();
+    find_attachments(json, false, progress, &mut attachments);
+    fs::create_dir_all(dir)?;
+    let mut taken = HashSet::new();
+    let mut written = Vec::new();
+    for attachment in attachments {
+        let label = ["filename", "name", "subject", "id"].iter()
+            .filter_map(|field| match &attachment[field] {
+                Value::String(s) => Some(sanitize_file_name(s)),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .find(|name| !name.is_empty())
+            .unwrap_or_else(|| String::from("attachment"));
+        let (data, extension) = match attachment_data(attachment) {
+            Some(Ok(decoded)) => decoded,
+            Some(Err(e)) => {
+                warn(opts, Failure::Parse, &format!("attachment {label} could not be decoded: {e}"), None);
+                continue;
+            },
+            None => {
+                warn(opts, Failure::NotFound, &format!("attachment {label} has no embedded data, it is left unresolved"), None);
+                continue;
+            },
+        };
+        let extension = if label.contains('.') { String::new() } else { extension };
+        let path = dir.join(claim_name(&mut taken, &label, &extension));
+        fs::write(&path, data)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// The reverse of `unescape` for newlines, tabs and backslashes, so that the
+/// text fits on a single line and can be turned back into the original.
+fn escape(s: &str) -> String {
+    let mut res = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            '\\' => res.push_str("\\\\"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Replace the escape sequences `\n`, `\t`, `\0` and `\\` given on the command
+/// line with the characters they stand for.
+fn unescape(s: &str) -> String {
+    let mut res = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('0') => res.push('\0'),
+            Some('\\') => res.push('\\'),
+            Some(other) => {
+                res.push('\\');
+                res.push(other);
+            },
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+/// Keep at most the first `max_chars` characters of `s`, counting grapheme
+/// clusters, so that neither a multi-byte UTF-8 character nor an emoji or
+/// letter made of several code points is cut in two.
+fn truncate(s: &str, max_chars: usize) -> &str {
+    match s.grapheme_indices(true).nth(max_chars) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Cut `s` after at most `max_bytes` bytes, backing off to the previous
+/// character boundary, and mark that it was cut.
+fn truncate_bytes(mut s: String, max_bytes: usize) -> String {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    s.truncate(end);
+    s.push_str("\u{2026}[truncated]");
+    s
+}
+
+/// Create a summary traversal of the notes json, printing these fields:
+/// {id, subject, content[:20], children}
+fn summary_traversal(json: &Value, opts: &Options, progress: &Progress) -> Result<Option<String>, serde_json::Error> {
+    Ok(serialize(opts, &summary_value(json, opts, progress)?))
+}
+
+/// The summary of the notes json, read into the model first. Fails when the
+/// json does not fit the model.
+fn summary_value(json: &Value, opts: &Options, progress: &Progress) -> Result<Value, serde_json::Error> {
+    Ok(file_summary(&NotesFile::deserialize(json)?, opts, progress))
+}
+
+/// A hash to detect changes to a note: the lowercase hex SHA-256 of the UTF-8
+/// subject, a NUL byte and the UTF-8 content, a missing field hashing like an
+/// empty one. The NUL keeps a subject ending in the start of the content, or
+/// the other way round, from hashing like the same note.
+fn note_hash(json: &Value) -> String {
+    fields_hash(json["subject"].as_str().unwrap_or_default(), &note_content(json))
+}
+
+fn fields_hash(subject: &str, content: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(subject);
+    hasher.update([0]);
+    hasher.update(content);
+    hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Serialize structured output as pretty-printed json, or as YAML with
+/// --yaml, where multi-line strings become block scalars.
+fn serialize(opts: &Options, value: &Value) -> Option<String> {
+    if opts.yaml() {
+        serde_yaml::to_string(value).ok().map(|yaml| String::from(yaml.trim_end()))
+    } else {
+        serde_json::to_string_pretty(value).ok()
+    }
+}
+/// The summary of a whole notes file, each root at depth 0.
+fn file_summary(file: &NotesFile, opts: &Options, progress: &Progress) -> Value {
+    match file {
+        NotesFile::Tree(root) => folder_summary(root, opts, progress, 0),
+        NotesFile::Trees(roots) => Value::Array(roots.iter().map(|root| folder_summary(root, opts, progress, 0)).collect()),
+    }
+}
+
+fn folder_summary(folder: &Folder, opts: &Options, progress: &Progress, depth: usize) -> Value {
+    let fields = SummaryFields {
+        id: folder.id.as_deref(),
+        subject: folder.subject.as_deref(),
+        content: folder.extra.get("content").and_then(Value::as_str),
+        extra: &folder.extra,
+    };
+    summary_helper(fields, &folder.children, opts, progress, depth)
+}
+
+fn note_summary(note: &Note, opts: &Options, progress: &Progress, depth: usize) -> Value {
+    let fields = SummaryFields {
+        id: note.id.as_deref(),
+        subject: note.subject.as_deref(),
+        content: note.content.as_deref(),
+        extra: &note.extra,
+    };
+    summary_helper(fields, &[], opts, progress, depth)
+}
+
+/// The fields of a note or folder of the model, for the summary.
+struct SummaryFields<'a> {
+    id: Option<&'a str>,
+    subject: Option<&'a str>,
+    content: Option<&'a str>,
+    extra: &'a Map<String, Value>,
+}
+
+/// The summary of a note or folder at `depth` folders below the root.
+fn summary_helper(note: SummaryFields, children: &[Entry], opts: &Options, progress: &Progress, depth: usize) -> Value {
+    let SummaryFields { id, subject, content, extra } = note;
+    progress.tick();
+    let mut res: Value = json!({});
+
+    let fields = match &opts.summary_fields {
+        Some(fields) => fields.iter().map(String::as_str).collect(),
+        None => vec!["id", "subject", "content"],
+    };
+    for field in fields {
+        match field {
+            "id" => if let Some(id) = id {
+                res["id"] = Value::String(String::from(id));
+            },
+            "subject" => if let Some(subject) = subject {
+                res["subject"] = Value::String(preview(subject, opts.truncate_subject).to_string());
+            },
+            "content" => if let Some(content) = content {
+                let content = preview(content, opts.truncate_content);
+                res["content"] = Value::String(match opts.indent_preview {
+                    // empty previews are left empty for --compact-empty
+                    true if !content.is_empty() => format!("{}{content}", "  ".repeat(depth)),
+                    _ => content.to_string(),
+                });
+            },
+            // the children are always shown, to keep the shape of the tree
+            "children" => {},
+            _ => match extra.get(field) {
+                None | Some(Value::Null) => {},
+                Some(value) => res[field] = value.clone(),
+            },
+        }
+    }
+    if opts.hash {
+        res["hash"] = Value::String(fields_hash(subject.unwrap_or_default(), content.unwrap_or_default()));
+    }
+
+    if !children.is_empty() {
+        // folders stay in the summary, only notes are left out by the dates
+        let children: Vec<&Entry> = children.iter()
+            .filter(|child| match child {
+                Entry::Folder(folder) => !opts.skips_folder(folder),
+                Entry::Note(note) => opts.added_in_range(note.extra.get("date_added").unwrap_or(&Value::Null)),
+            })
+            .collect();
+        let (head, tail) = match (opts.head, opts.tail) {
+            _ if opts.max_depth.is_some_and(|max_depth| depth >= max_depth) => (0, 0),
+            (None, None) => (children.len(), 0),
+            (head, tail) => (head.unwrap_or(0), tail.unwrap_or(0)),
+        };
+        let summary = |child: &&Entry| match child {
+            Entry::Folder(folder) => folder_summary(folder, opts, progress, depth + 1),
+            Entry::Note(note) => note_summary(note, opts, progress, depth + 1),
+        };
+        let mut parsed_children: Vec<Value> = Vec::new();
+        if head + tail >= children.len() {
+            parsed_children.extend(children.iter().map(summary));
+        } else {
+            parsed_children.extend(children[..head].iter().map(summary));
+            parsed_children.push(json!({"omitted": children.len() - head - tail}));
+            parsed_children.extend(children[children.len() - tail..].iter().map(summary));
+        }
+        res["children"] = Value::Array(parsed_children);
+    }
+    if let (true, Value::Object(fields)) = (opts.compact_empty, &mut res) {
+        fields.retain(|_, value| !is_empty_value(value));
+    }
+    res
+}
+
+/// Characters of a field shown in the summary unless chosen otherwise.
+const SUMMARY_PREVIEW_CHARS: usize = 30;
+
+/// Shorten a field for the summary, where a length of 0 keeps all of it. A
+/// shortened field ends in an ellipsis.
+fn preview(s: &str, max_chars: Option<usize>) -> Cow<'_, str> {
+    let preview = match max_chars.unwrap_or(SUMMARY_PREVIEW_CHARS) {
+        0 => s,
+        max_chars => truncate(s, max_chars),
+    };
+    if preview.len() < s.len() {
+        Cow::Owned(format!("{preview}\u{2026}"))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(fields) => fields.is_empty(),
+        _ => false,
+    }
+}
+
+/// Rebuild the json with the keys of every object in sorted order, so that the
+/// serialization does not depend on the order of keys in the source file.
+fn normalize_json(json: &Value) -> Value {
+    match json {
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by_key(|(k, _)| *k);
+            let mut normalized = serde_json::Map::new();
+            for (k, v) in entries {
+                normalized.insert(k.to_string(), normalize_json(v));
+            }
+            Value::Object(normalized)
+        },
+        Value::Array(values) => Value::Array(values.iter().map(normalize_json).collect()),
+        _ => json.clone(),
+    }
+}
+
+/// Fan-out of the folders in the notes tree.
+#[derive(Default)]
+struct Branching {
+    folders: usize,
+    children: usize,
+    max_children: usize,
+    widest_id: Option<String>,
+}
+
+impl Branching {
+    /// Average number of children per folder, leaves are not counted.
+    fn average(&self) -> f64 {
+        if self.folders == 0 {
+            0.0
+        } else {
+            self.children as f64 / self.folders as f64
+        }
+    }
+}
+
+/// Accumulate the fan-out of every folder in the tree into `branching`.
+fn branching_traversal(json: &Value, progress: &Progress, branching: &mut Branching) {
+    progress.tick();
+    if let Some(children) = note_children(json) {
+        branching.folders += 1;
+        branching.children += children.len();
+        if children.len() > branching.max_children {
+            branching.max_children = children.len();
+            branching.widest_id = json["id"].as_str().map(String::from);
+        }
+        for child in children {
+            branching_traversal(child, progress, branching);
+        }
+    }
+}
+
+/// The children of a note or of an array of roots, for editing.
+fn children_mut(json: &mut Value) -> Option<&mut Vec<Value>> {
+    match json {
+        Value::Array(roots) => Some(roots),
+        _ => json.get_mut("children").and_then(Value::as_array_mut),
+    }
+}
+
+fn collect_ids(json: &Value, ids: &mut HashSet<String>) {
+    if let Some(id) = json["id"].as_str() {
+        ids.insert(String::from(id));
+    }
+    for child in note_children(json).into_iter().flatten() {
+        collect_ids(child, ids);
+    }
+}
+
+fn index_notes<'a>(json: &'a Value, index: &mut HashMap<String, &'a Value>) {
+    if let Some(id) = json["id"].as_str() {
+        index.insert(String::from(id), json);
+    }
+    for child in note_children(json).into_iter().flatten() {
+        index_notes(child, index);
+    }
+}
+
+/// Copy the primary tree, replacing each note by the note with the same id in
+/// the secondary tree when that one was modified later. Folders keep their
+/// place and fields from the primary tree.
+fn merge_primary(json: &Value, secondary: &HashMap<String, &Value>) -> Value {
+    if let Some(children) = note_children(json) {
+        let mut merged = json.clone();
+        if let Some(merged_children) = children_mut(&mut merged) {
+            *merged_children = children.iter().map(|child| merge_primary(child, secondary)).collect();
+        }
+        return merged;
+    }
+    let newer = json["id"].as_str()
+        .and_then(|id| secondary.get(id))
+        .filter(|other| note_modified(other) > note_modified(json));
+    match newer {
+        Some(other) => (*other).clone(),
+        None => json.clone(),
+    }
+}
+
+/// Copy a subtree that is new to the primary tree, leaving out the notes in it
+/// that the primary tree already has elsewhere.
+fn prune_known(json: &Value, primary_ids: &HashSet<String>) -> Value {
+    let mut pruned = json.clone();
+    if let (Some(children), Some(pruned_children)) = (note_children(json), children_mut(&mut pruned)) {
+        *pruned_children = children.iter()
+            .filter(|child| !child["id"].as_str().is_some_and(|id| primary_ids.contains(id)))
+            .map(|child| prune_known(child, primary_ids))
+            .collect();
+    }
+    pruned
+}
+
+fn find_by_id_mut<'a>(json: &'a mut Value, id: &str) -> Option<&'a mut Value> {
+    if json["id"].as_str() == Some(id) {
+        return Some(json);
+    }
+    children_mut(json)?.iter_mut().find_map(|child| find_by_id_mut(child, id))
+}
+
+/// Append the notes of the secondary folder `json` that the primary tree does
+/// not have to the children of `target`, the matching folder of the merged tree.
+fn append_new(json: &Value, target: &mut Value, primary_ids: &HashSet<String>) {
+    for child in note_children(json).into_iter().flatten() {
+        match child["id"].as_str() {
+            Some(id) if primary_ids.contains(id) => {
+                if note_children(child).is_none() {
+                    continue;
+                }
+                if let Some(folder) = find_by_id_mut(target, id) {
+                    append_new(child, folder, primary_ids);
+                }
+            },
+            _ => {
+                if let Some(children) = children_mut(target) {
+                    children.push(prune_known(child, primary_ids));
+                }
+            },
+        }
+    }
+}
+
+/// Merge the secondary notes tree into the primary one. The folder structure
+/// of the primary tree is kept, notes with the same id keep the version that
+/// was modified last, and notes only in the secondary tree are appended to the
+/// same folder, or to the root if their folder is new as well.
+fn merge_notes(primary: &Value, secondary: &Value) -> Value {
+    let mut primary_ids = HashSet::new();
+    collect_ids(primary, &mut primary_ids);
+    let mut secondary_index = HashMap::new();
+    index_notes(secondary, &mut secondary_index);
+
+    let mut merged = merge_primary(primary, &secondary_index);
+    append_new(secondary, &mut merged, &primary_ids);
+    merged
+}
+
+/// Replace `from` by `to` in the content of every note that matches the query
+/// and return the number of replacements. Other notes are left untouched.
+fn replace_in_notes(query: &Query, opts: &Options, json: &mut Value, from: &str, to: &str) -> usize {
+    if note_children(json).is_some() {
+        return children_mut(json).into_iter().flatten()
+            .filter(|child| !opts.skips(child))
+            .map(|child| replace_in_notes(query, opts, child, from, to))
+            .sum();
+    }
+    if !note_matches(query, opts, json) {
+        return 0;
+    }
+    let Some(Value::String(content)) = json.get_mut("content") else {
+        return 0;
+    };
+    let count = content.matches(from).count();
+    if count > 0 {
+        *content = content.replace(from, to);
+    }
+    count
+}
+
+/// Read the notes json from an entry of a zip archive. Without an explicit
+/// entry name, the archive must hold exactly one entry whose file name is
+/// `Notes`, wherever it is in the archive.
+fn read_zip_entry<R: Read + Seek>(reader: R, entry: Option<&str>) -> Result<String, String> {
+    let mut archive = zip::ZipArchive::new(reader)
+        .map_err(|e| format!("could not open zip archive: {e}"))?;
+    let name = match entry {
+        Some(entry) => String::from(entry),
+        None => {
+            let notes: Vec<&str> = archive.file_names()
+                .filter(|name| name.rsplit('/').next() == Some("Notes"))
+                .collect();
+            match notes[..] {
+                [name] => String::from(name),
+                [] => return Err(String::from("no Notes entry in zip archive, choose one with --entry")),
+                _ => return Err(format!("several Notes entries in zip archive, choose one with --entry: {}", notes.join(", "))),
+            }
+        },
+    };
+    let mut file = archive.by_name(&name)
+        .map_err(|e| format!("could not read entry '{name}' from zip archive: {e}"))?;
+    let mut notes_json = String::new();
+    file.read_to_string(&mut notes_json)
+        .map_err(|e| format!("could not read entry '{name}' from zip archive: {e}"))?;
+    Ok(notes_json)
+}
+
+/// Parse json like `serde_json::from_str`, but fail on an object with the same
+/// key twice instead of keeping only the last value.
+fn parse_strict(json: &str) -> Result<Value, serde_json::Error> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let StrictValue(value) = StrictValue::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(value)
+}
+
+/// A json value that rejects duplicate keys while it is deserialized.
+struct StrictValue(Value);
+
+impl<'de> Deserialize<'de> for StrictValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StrictValue, D::Error> {
+        deserializer.deserialize_any(StrictVisitor).map(StrictValue)
+    }
+}
+
+struct StrictVisitor;
+
+impl<'de> Visitor<'de> for StrictVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any json value")
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Value, E> {
+        Ok(Value::Bool(b))
+    }
+
+    fn visit_i64<E>(self, n: i64) -> Result<Value, E> {
+        Ok(Value::from(n))
+    }
+
+    fn visit_u64<E>(self, n: u64) -> Result<Value, E> {
+        Ok(Value::from(n))
+    }
+
+    fn visit_f64<E>(self, n: f64) -> Result<Value, E> {
+        Ok(Value::from(n))
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Value, E> {
+        Ok(Value::from(s))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Value, E> {
+        Ok(Value::String(s))
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(StrictValue(value)) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut fields = serde_json::Map::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if fields.contains_key(&key) {
+                return Err(de::Error::custom(format!("duplicate key '{key}'")));
+            }
+            let StrictValue(value) = map.next_value()?;
+            fields.insert(key, value);
+        }
+        Ok(Value::Object(fields))
+    }
+}
+
+/// The layout of the notes file of a Vivaldi release. The rest of the parser
+/// reads the field names of `Vivaldi6`; files of other releases are renamed
+/// to them when loaded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Schema {
+    Vivaldi5,
+    Vivaldi6,
+}
+
+impl Schema {
+    /// The fields that this release names differently, as pairs of its name
+    /// and the name used by the parser.
+    fn renames(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Schema::Vivaldi5 => &[("dateCreated", "date_added"), ("dateModified", "date_modified")],
+            Schema::Vivaldi6 => &[],
+        }
+    }
+
+    /// Guess the release from the first note, depth-first, that has one of
+    /// the fields they name differently. Files without any are read as
+    /// `Vivaldi6`.
+    fn detect(json: &Value) -> Schema {
+        Schema::probe(json).unwrap_or(Schema::Vivaldi6)
+    }
+
+    fn probe(json: &Value) -> Option<Schema> {
+        for (old, new) in Schema::Vivaldi5.renames() {
+            if json.get(old).is_some() {
+                return Some(Schema::Vivaldi5);
+            }
+            if json.get(new).is_some() {
+                return Some(Schema::Vivaldi6);
+            }
+        }
+        note_children(json).into_iter().flatten().find_map(Schema::probe)
+    }
+}
+
+/// Rename the fields of every note in the tree, `renames` being pairs of the
+/// old and the new name.
+fn rename_fields(json: &mut Value, renames: &[(&str, &str)]) {
+    if let Value::Object(fields) = json {
+        for (from, to) in renames {
+            if let Some(value) = fields.remove(*from) {
+                fields.insert(String::from(*to), value);
+            }
+        }
+    }
+    for child in children_mut(json).into_iter().flatten() {
+        rename_fields(child, renames);
+    }
+}
+
+/// Rename the fields of the notes from the schema given with --schema, or
+/// the detected one, to the names the parser reads. Returns that schema.
+fn adapt_schema(opts: &Options, json: &mut Value) -> Schema {
+    let schema = opts.schema.unwrap_or_else(|| Schema::detect(json));
+    rename_fields(json, schema.renames());
+    schema
+}
+
+/// When results are shown through a pager.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Pager {
+    /// When stdout is a terminal and the results do not fit on it.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Prints the results. When stdout is a terminal they are collected instead
+/// and shown through the pager once the run is over, even if it fails.
+struct Output {
+    pager: Pager,
+    collected: Option<String>,
+    /// --separator, printed between the outputs instead of ending each of
+    /// them with a newline.
+    separator: Option<String>,
+    printed: bool,
+}
+
+impl Output {
+    fn new(pager: Pager, separator: Option<String>) -> Output {
+        let collect = pager != Pager::Never && io::stdout().is_terminal();
+        Output { pager, collected: collect.then(String::new), separator, printed: false }
+    }
+
+    fn print(&mut self, text: &str) {
+        match self.separator.take() {
+            Some(separator) => {
+                if self.printed {
+                    self.write(&separator);
+                }
+                self.write(text);
+                self.separator = Some(separator);
+            },
+            None => {
+                self.write(text);
+                self.write("\n");
+            },
+        }
+        self.printed = true;
+    }
+
+    fn write(&mut self, text: &str) {
+        match &mut self.collected {
+            Some(collected) => collected.push_str(text),
+            None => print!("{text}"),
+        }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let Some(collected) = self.collected.take() else {
+            let _ = io::stdout().flush();
+            return;
+        };
+        // $LINES is only set by some shells, assume a classic terminal otherwise
+        let height = env::var("LINES").ok().and_then(|lines| lines.parse().ok()).unwrap_or(24);
+        if (self.pager == Pager::Always || collected.lines().count() >= height) && page(&collected).is_ok() {
+            return;
+        }
+        print!("{collected}");
+    }
+}
+
+/// Print the output of a query, numbering its lines with --numbered.
+fn print_output(out: &mut Output, opts: &Options, text: &str) {
+    if opts.numbered {
+        out.print(&number_lines(text));
+    } else {
+        out.print(text);
+    }
+}
+
+/// Show the text through $PAGER, or less if it is not set.
+fn page(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| String::from("less"));
+    let mut words = pager.split_whitespace();
+    let program = words.next().unwrap_or("less");
+    let mut child = process::Command::new(program).args(words).stdin(process::Stdio::piped()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // the pager may be quit before it read everything
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Time spent in each phase of a run, summed over all inputs.
+#[derive(Default)]
+struct Timing {
+    reading: Duration,
+    parsing: Duration,
+    traversal: Duration,
+}
+
+impl Timing {
+    fn report(&self) {
+        let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        eprintln!(
+            "timing: reading {:.1} ms, parsing {:.1} ms, traversal {:.1} ms",
+            millis(self.reading),
+            millis(self.parsing),
+            millis(self.traversal)
+        );
+    }
+}
+
+/// The ways a run can fail, each with its own exit status.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Failure {
+    NotFound,
+    Io,
+    Parse,
+    /// Some of the inputs were skipped with --ignore-missing-file.
+    Partial,
+    Args,
+}
+
+impl Failure {
+    fn name(self) -> &'static str {
+        match self {
+            Failure::NotFound => "not_found",
+            Failure::Io => "io",
+            Failure::Parse => "parse",
+            Failure::Partial => "partial",
+            Failure::Args => "args",
+        }
+    }
+
+    fn exit_code(self) -> ExitCode {
+        ExitCode::from(match self {
+            Failure::NotFound => 1,
+            Failure::Io => 3,
+            Failure::Parse => 4,
+            Failure::Partial => 5,
+            Failure::Args => 2,
+        })
+    }
+}
+
+/// Format an error for stderr. Nothing is reported in text format when
+/// nothing was found, the exit status says enough there.
+fn format_error(format: ErrorFormat, failure: Failure, message: &str, file: Option<&str>) -> Option<String> {
+    match format {
+        ErrorFormat::Text if failure == Failure::NotFound => None,
+        ErrorFormat::Text => Some(format!("error: {message}")),
+        ErrorFormat::Json => {
+            let mut error = json!({"error": failure.name(), "message": message});
+            if let Some(file) = file {
+                error["file"] = Value::String(String::from(file));
+            }
+            Some(error.to_string())
+        },
+    }
+}
+
+/// Report the error on stderr and return the exit status that goes with it.
+fn fail(opts: &Options, failure: Failure, message: &str, file: Option<&str>) -> ExitCode {
+    match (opts.error_format, format_error(opts.error_format, failure, message, file)) {
+        (ErrorFormat::Text, Some(_)) => eprintln!("{} {message}", label(opts.color, "error")),
+        (_, Some(error)) => eprintln!("{error}"),
+        (_, None) => {},
+    }
+    failure.exit_code()
+}
+
+/// Report a problem that does not stop the run, e.g. an input that is skipped
+/// with --ignore-missing-file.
+fn warn(opts: &Options, failure: Failure, message: &str, file: Option<&str>) {
+    match opts.error_format {
+        ErrorFormat::Text => eprintln!("{} {message}", label(opts.color, "warning")),
+        ErrorFormat::Json => eprintln!("{}", format_error(ErrorFormat::Json, failure, message, file).unwrap_or_default()),
+    }
+}
+
+/// Read the paths of a --files-from list, separated by NUL bytes as printed by
+/// `find -print0`.
+fn read_file_list(list: &str) -> Result<Vec<String>, String> {
+    let mut bytes = Vec::new();
+    if list == "-" {
+        io::stdin().lock().read_to_end(&mut bytes).map_err(|e| read_error("stdin", &e))?;
+    } else {
+        bytes = fs::read(list).map_err(|e| read_error(list, &e))?;
+    }
+    Ok(split_file_list(&bytes))
+}
+
+fn split_file_list(bytes: &[u8]) -> Vec<String> {
+    bytes.split(|&b| b == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| String::from_utf8_lossy(path).into_owned())
+        .collect()
+}
+
+/// Remove the UTF-8 byte order mark that some Windows tools put in front of
+/// exported files, which the json parser rejects. Also tells whether there
+/// was one.
+fn strip_bom(notes_json: &str) -> (&str, bool) {
+    match notes_json.strip_prefix('\u{FEFF}') {
+        Some(stripped) => (stripped, true),
+        None => (notes_json, false),
+    }
+}
+
+/// Describe why a file could not be read, without the raw os error code for
+/// the common cases.
+fn read_error(file: &str, e: &io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => format!("could not read '{file}': file not found"),
+        io::ErrorKind::PermissionDenied => format!("could not read '{file}': permission denied"),
+        _ => format!("could not read '{file}': {e}"),
+    }
+}
+
+impl Input {
+    /// The file the notes are read from, if any.
+    fn file_name(&self) -> Option<&str> {
+        match self {
+            Input::File(file) => Some(file),
+            Input::Zip { archive, .. } => Some(archive),
+            Input::FilesFrom(list) => Some(list),
+            Input::Stdin => None,
+        }
+    }
+
+    /// The inputs to run the query on, one for each file of a --files-from
+    /// list.
+    fn expand(self) -> Result<Vec<Input>, String> {
+        match self {
+            Input::FilesFrom(list) => Ok(read_file_list(&list)?.into_iter().map(Input::File).collect()),
+            input => Ok(vec![input]),
+        }
+    }
+
+    /// Check that the file or archive can be opened, without reading it.
+    fn check(&self) -> Result<(), String> {
+        match self {
+            Input::File(file) | Input::Zip { archive: file, .. } => {
+                fs::File::open(file).map(drop).map_err(|e| read_error(file, &e))
+            },
+            Input::FilesFrom(_) | Input::Stdin => Ok(()),
+        }
+    }
+
+    /// Read and parse the notes. The raw text is kept as well for
+    /// --byte-offset, which has to search it.
+    fn load(&self, opts: &Options, timing: &mut Timing) -> Result<(Value, Option<String>), (Failure, String)> {
+        let start = Instant::now();
+        let raw = self.read().map_err(|message| (Failure::Io, message))?;
+        timing.reading += start.elapsed();
+        let start = Instant::now();
+        let (notes_json, had_bom) = strip_bom(&raw);
+        if had_bom {
+            let message = format!("removed byte order mark from {}", self.file_name().unwrap_or("stdin"));
+            warn(opts, Failure::Parse, &message, self.file_name());
+        }
+        // the notes are checked against the model, so that a malformed file
+        // fails here rather than somewhere in the traversal
+        let parsed = if opts.strict_json { parse_strict(notes_json) } else { serde_json::from_str(notes_json) }
+            .and_then(|notes: Value| NotesFile::deserialize(&notes).map(|_| notes))
+            .map_err(|e| (Failure::Parse, format!("could not parse notes: {e}")));
+        timing.parsing += start.elapsed();
+        Ok((parsed?, if opts.byte_offset { Some(raw) } else { None }))
+    }
+
+    fn read(&self) -> Result<String, String> {
+        match self {
+            Input::File(file) => fs::read_to_string(file).map_err(|e| read_error(file, &e)),
+            Input::Zip { archive, entry } => {
+                let reader = fs::File::open(archive).map_err(|e| read_error(archive, &e))?;
+                read_zip_entry(reader, entry.as_deref())
+            },
+            Input::FilesFrom(list) => Err(format!("'{list}' is a list of files, not notes")),
+            Input::Stdin => read_all(io::stdin().lock(), "stdin"),
+        }
+    }
+}
+
+/// Read all of `reader` as it is, line breaks included, so that byte offsets
+/// into the text are the same as in the file it came from.
+fn read_all(mut reader: impl Read, name: &str) -> Result<String, String> {
+    let mut buf = String::new();
+    reader.read_to_string(&mut buf).map_err(|e| read_error(name, &e))?;
+    Ok(buf)
+}
+
+/// Format a value found by a JSON Pointer: strings are printed as they are,
+/// anything else as pretty-printed json or YAML.
+fn pointer_output(opts: &Options, value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(String::from(s)),
+        _ => serialize(opts, value),
+    }
+}
+
+/// Split a REPL query line into words, keeping text between double quotes
+/// together. Returns `None` if a quote is left open.
+fn split_query(line: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            },
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            },
+            c => {
+                word.push(c);
+                in_word = true;
+            },
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if in_word {
+        words.push(word);
+    }
+    // shorthand lines like `k subject c Todo` alternate flags and values
+    if words.first().is_some_and(|w| !w.starts_with('-')) {
+        for flag in words.iter_mut().step_by(2) {
+            if !flag.starts_with('-') {
+                flag.insert(0, '-');
+            }
+        }
+    }
+    Some(words)
+}
+
+/// Read query lines from stdin and answer each of them from the notes that
+/// were parsed once up front. Malformed lines are reported and skipped.
+fn repl(opts: &Options, notes_json: &Value) -> Result<(), Box<dyn Error>> {
+    let interactive = io::stdin().is_terminal();
+    let prompt = || if interactive { eprint!("> ") };
+    prompt();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            prompt();
+            continue;
+        }
+        match answer_query(&line, notes_json) {
+            Ok(Some(output)) => println!("{output}"),
+            Ok(None) => {},
+            Err(message) => eprintln!("{} {message}", label(opts.color, "error")),
+        }
+        prompt();
+    }
+    Ok(())
+}
+
+/// Run one query line of the REPL or of --serve, in the same syntax as the
+/// command line or its shorthand.
+fn answer_query(line: &str, notes_json: &Value) -> Result<Option<String>, String> {
+    let words = split_query(line).ok_or_else(|| format!("unterminated quote in query: {line}"))?;
+    let args = std::iter::once(String::from("repl")).chain(words);
+    match parse_args(args, None) {
+        // a line without a field would fall back to the whole summary
+        Args::Key {key, val, contains, opts, ..}
+            if !opts.repl && opts.serve.is_none()
+                && (key.is_some() || opts.expr.is_some() || opts.any_field || opts.combined_field || opts.filters_alone() || opts.pointer.is_some() || opts.note_path.is_some()) => {
+            let root = query_root(&opts, notes_json)?;
+            let output = run_query(key, &val, &contains, &opts, root, None).map_err(|e| e.to_string())?;
+            Ok(if opts.numbered { output.map(|output| number_lines(&output)) } else { output })
+        },
+        Args::BadArgs(reason) => Err(format!("invalid query: {line}: {reason}")),
+        _ => Err(format!("invalid query: {line}")),
+    }
+}
+
+/// The folder a query line of the REPL or of --serve is run against: its
+/// --root-path and --root are looked up in the notes of the session.
+fn query_root<'a>(opts: &Options, notes_json: &'a Value) -> Result<&'a Value, String> {
+    let root = notes_root(opts, notes_json)?;
+    let Some(folder) = &opts.root_folder else {
+        return Ok(root);
+    };
+    let mut path = Vec::new();
+    find_folder(opts, root, folder, &mut path)
+        .then(|| note_at(root, &path))
+        .flatten()
+        .ok_or_else(|| format!("no folder '{folder}' for --root"))
+}
+
+/// Listen on a unix socket and answer one query per connection: the client
+/// sends a query line and reads the matches until the connection is closed.
+#[cfg(unix)]
+fn serve(opts: &Options, path: &str, notes_json: &Value) -> Result<(), Box<dyn Error>> {
+    let listener = UnixListener::bind(path).map_err(|e| format!("could not listen on '{path}': {e}"))?;
+    eprintln!("serving queries on {path}");
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| answer_connection(stream, notes_json));
+        if let Err(e) = result {
+            // one broken client must not stop the others from being served
+            eprintln!("{} connection failed: {e}", label(opts.color, "error"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn answer_connection(mut stream: UnixStream, notes_json: &Value) -> io::Result<()> {
+    let mut line = String::new();
+    io::BufReader::new(&stream).read_line(&mut line)?;
+    match answer_query(line.trim_end(), notes_json) {
+        Ok(Some(output)) => writeln!(stream, "{output}"),
+        Ok(None) => Ok(()),
+        Err(message) => writeln!(stream, "error: {message}"),
+    }
+}
+
+/// Run a single query against the parsed notes and return what should be
+/// printed, or `None` if nothing was found. `raw` is the text the notes were
+/// parsed from, when --byte-offset needs it.
+fn run_query(
+    key: Option<String>,
+    val: &Option<String>,
+    contains: &Option<String>,
+    opts: &Options,
+    notes_json: &Value,
+    raw: Option<&str>
+) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(find_output(key, val, contains, opts, notes_json, raw)?.and_then(|found| found.render(opts)))
+}
+
+/// What a query found, before it is printed. Arrays and outlines are kept
+/// apart from text, so that the results of several inputs can be printed as
+/// one document.
+enum Found {
+    Text(String),
+    /// The items of a json array, with --json-array or --flatten.
+    Array(Vec<Value>),
+    /// The outlines of the body of an OPML document.
+    Outlines(String),
+}
+
+impl Found {
+    fn render(self, opts: &Options) -> Option<String> {
+        match self {
+            Found::Text(text) => Some(text),
+            Found::Array(items) => serialize(opts, &Value::Array(items)),
+            Found::Outlines(outlines) => Some(opml(&outlines)),
+        }
+    }
+
+    /// Add what was found in another input to an array or outlines.
+    fn extend(&mut self, other: Found) {
+        match (self, other) {
+            (Found::Array(items), Found::Array(more)) => items.extend(more),
+            (Found::Outlines(outlines), Found::Outlines(more)) => outlines.push_str(&more),
+            // text is printed as soon as it is found, and every input is
+            // queried with the same options
+            _ => {},
+        }
+    }
+}
+
+/// `run_query`, without printing what it found.
+fn find_output(
+    key: Option<String>,
+    val: &Option<String>,
+    contains: &Option<String>,
+    opts: &Options,
+    notes_json: &Value,
+    raw: Option<&str>
+) -> Result<Option<Found>, Box<dyn Error>> {
+    if let Some(pointer) = &opts.pointer {
+        // the traversal is skipped entirely, the pointer addresses the note
+        return Ok(notes_json.pointer(pointer).and_then(|value| pointer_output(opts, value)).map(Found::Text));
+    }
+
+    if opts.normalize {
+        return Ok(serialize(opts, &normalize_json(notes_json)).map(Found::Text));
+    }
+
+    if let Some(note_path) = &opts.note_path {
+        let segments: Vec<&str> = note_path.split('/').filter(|segment| !segment.is_empty()).collect();
+        let mut matches = Vec::new();
+        resolve_path(opts, notes_json, &segments, &mut Vec::new(), &mut matches);
+        let contents: Vec<String> = matches.iter().filter_map(|m| render_match(opts, notes_json, m)).collect();
+        let separator = opts.separator.as_deref().unwrap_or("\n");
+        return Ok(if contents.is_empty() { None } else { Some(Found::Text(contents.join(separator))) });
+    }
+
+    let progress = Progress::new(opts.progress);
+    if opts.branching {
+        let mut branching = Branching::default();
+        branching_traversal(notes_json, &progress, &mut branching);
+        progress.finish();
+        return Ok(Some(Found::Text(format!(
+            "max children: {}\naverage children: {:.2}\nwidest folder: {}",
+            branching.max_children,
+            branching.average(),
+            branching.widest_id.as_deref().unwrap_or("(none)")
+        ))));
+    }
+    if let Some(n) = opts.top_longest {
+        let longest = top_longest(notes_json, &progress, n);
+        progress.finish();
+        let lines: Vec<String> = longest.iter().map(|(subject, len)| format!("{subject}: {len}")).collect();
+        return Ok(if lines.is_empty() { None } else { Some(Found::Text(lines.join("\n"))) });
+    }
+    if opts.folder_counts {
+        let counts = folder_counts(notes_json, &progress);
+        progress.finish();
+        let lines: Vec<String> = counts.iter().map(|(subject, count)| format!("{subject}: {count}")).collect();
+        return Ok(if lines.is_empty() { None } else { Some(Found::Text(lines.join("\n"))) });
+    }
+    if let Some(dir) = &opts.extract_attachments {
+        let written = extract_attachments(Path::new(dir), opts, notes_json, &progress)?;
+        progress.finish();
+        let paths: Vec<String> = written.iter().map(|path| path.display().to_string()).collect();
+        return Ok(if paths.is_empty() { None } else { Some(Found::Text(paths.join("\n"))) });
+    }
+    if opts.flatten {
+        let notes = flatten(notes_json, opts, &progress);
+        progress.finish();
+        return Ok(if notes.is_empty() { None } else { Some(Found::Array(notes)) });
+    }
+    if opts.keys {
+        let mut keys = BTreeSet::new();
+        collect_keys(notes_json, &progress, &mut keys);
+        progress.finish();
+        let lines: Vec<&str> = keys.into_iter().collect();
+        return Ok(if lines.is_empty() { None } else { Some(Found::Text(lines.join("\n"))) });
+    }
+    let content = match Query::from_args(key, val.clone(), contains.clone(), opts) {
+        Some(query) if opts.all || opts.sample.is_some() => {
+            let mut matches = Vec::new();
+            traverse_json_all(&query, opts, &progress, notes_json, &mut Vec::new(), &mut matches);
+            if let Some(limit) = opts.match_limit_per_file {
+                // every input is queried on its own, so this limits each file
+                matches.truncate(limit);
+            }
+            if let Some(n) = opts.sample {
+                matches = sample_matches(matches, n, opts.seed);
+            }
+            if opts.byte_offset {
+                progress.finish();
+                return Ok(byte_offset_output(raw, &query, &matches)?.map(Found::Text));
+            }
+            if opts.rank {
+                // stable sort, so equally relevant notes keep their tree order
+                matches.sort_by_key(|m| Reverse(relevance(opts, m.note, query.text())));
+            }
+            if let Some(dir) = &opts.output_dir {
+                let written = write_matches(Path::new(dir), opts, &matches)?;
+                let paths: Vec<String> = written.iter().map(|path| path.display().to_string()).collect();
+                return Ok(if paths.is_empty() { None } else { Some(Found::Text(paths.join("\n"))) });
+            }
+            if opts.json_array {
+                progress.finish();
+                return Ok(if matches.is_empty() { None } else { Some(Found::Array(json_array(opts, &matches))) });
+            }
+            if opts.opml {
+                progress.finish();
+                let notes: Vec<&Value> = matches.iter().map(|m| m.note).collect();
+                return Ok(if notes.is_empty() { None } else { Some(Found::Outlines(opml_outlines(&notes))) });
+            }
+            if opts.group_by_folder {
+                progress.finish();
+                return Ok(group_by_folder(opts, notes_json, &matches).map(Found::Text));
+            }
+            let mut contents: Vec<String> = matches.iter().filter_map(|m| {
+                let output = render_match(opts, notes_json, m)?;
+                let count = || matching_form(opts, &note_content(m.note)).matches(&*matching_form(opts, query.text())).count();
+                Some(if opts.occurrences { format!("{}\t{output}", count()) } else { output })
+            }).collect();
+            if opts.unique {
+                let mut seen = HashSet::new();
+                contents.retain(|content| seen.insert(content.clone()));
+            }
+            let separator = opts.separator.as_deref().unwrap_or("\n");
+            if contents.is_empty() { None } else { Some(Found::Text(contents.join(separator))) }
+        },
+        Some(query) => {
+            let found = if let Some(n) = opts.nth {
+                traverse_json_nth(&query, opts, &progress, notes_json, &mut Vec::new(), &mut n.clone())
+            } else if opts.last {
+                traverse_json_last(&query, opts, &progress, notes_json, &mut Vec::new())
+            } else {
+                traverse_json(&query, opts, &progress, notes_json, &mut Vec::new())
+            };
+            match found {
+                Some(m) if opts.byte_offset => {
+                    progress.finish();
+                    return Ok(byte_offset_output(raw, &query, &[m])?.map(Found::Text));
+                },
+                Some(m) if opts.opml => Some(Found::Outlines(opml_outlines(&[m.note]))),
+                Some(m) => render_match(opts, notes_json, &m).map(Found::Text),
+                None => None,
+            }
+        },
+        // the root itself has no subject, so the outline starts at its children
+        _ if opts.opml => {
+            Some(Found::Outlines(opml_outlines(&top_level_notes(notes_json))))
+        },
+        _ => summary_traversal(notes_json, opts, &progress)?.map(Found::Text),
+    };
+    progress.finish();
+    Ok(content)
+}
+
+/// The matches bucketed by the folder they are in, each group headed by the
+/// folder and its number of matches. Groups come in the order of their first
+/// match, notes directly under the root go in `(root)`.
+fn group_by_folder(opts: &Options, root: &Value, matches: &[Match]) -> Option<String> {
+    let mut groups: Vec<(&[usize], Vec<String>)> = Vec::new();
+    for m in matches {
+        let parent_path = m.path.split_last().map(|(_, parent_path)| parent_path).unwrap_or_default();
+        let Some(output) = render_match(opts, root, m) else {
+            continue;
+        };
+        match groups.iter_mut().find(|(path, _)| *path == parent_path) {
+            Some((_, outputs)) => outputs.push(output),
+            None => groups.push((parent_path, vec![output])),
+        }
+    }
+    let sections: Vec<String> = groups.iter().map(|(parent_path, outputs)| {
+        let heading = match note_at(root, parent_path) {
+            Some(parent) if !parent_path.is_empty() => note_label(parent),
+            _ => String::from("(root)"),
+        };
+        let noun = if outputs.len() == 1 { "match" } else { "matches" };
+        format!("{heading}: [{} {noun}]\n{}", outputs.len(), outputs.join("\n"))
+    }).collect();
+    if sections.is_empty() { None } else { Some(sections.join("\n\n")) }
+}
+
+/// An OPML document with the outlines of `opml_outlines` as its body.
+fn opml(outlines: &str) -> String {
+    let mut res = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+    res.push_str("  <head>\n    <title>Vivaldi notes</title>\n  </head>\n  <body>\n");
+    res.push_str(outlines);
+    res.push_str("  </body>\n</opml>");
+    res
+}
+
+/// The outlines of the notes: folders become outlines holding their children
+/// and notes outlines with their content as the `_note` attribute.
+fn opml_outlines(notes: &[&Value]) -> String {
+    let mut res = String::new();
+    for note in notes {
+        opml_outline(note, 2, &mut res);
+    }
+    res
+}
+
+fn opml_outline(json: &Value, depth: usize, res: &mut String) {
+    let indent = "  ".repeat(depth);
+    let text = escape_xml_attribute(&note_label(json));
+    let Some(children) = note_children(json) else {
+        match json["content"].as_str() {
+            Some(content) => res.push_str(&format!("{indent}<outline text=\"{text}\" _note=\"{}\"/>\n", escape_xml_attribute(content))),
+            None => res.push_str(&format!("{indent}<outline text=\"{text}\"/>\n")),
+        }
+        return;
+    };
+    res.push_str(&format!("{indent}<outline text=\"{text}\">\n"));
+    for child in children {
+        opml_outline(child, depth + 1, res);
+    }
+    res.push_str(&format!("{indent}</outline>\n"));
+}
+
+/// Escape text for a double quoted XML attribute. Line breaks and tabs become
+/// character references, which XML would otherwise read as spaces, and the
+/// other control characters, which XML 1.0 does not allow, are dropped.
+fn escape_xml_attribute(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            '\n' => res.push_str("&#10;"),
+            '\r' => res.push_str("&#13;"),
+            '\t' => res.push_str("&#9;"),
+            c if (c as u32) < 0x20 => {},
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// Find the notes at the end of a path of subjects, walking down one folder
+/// level for every segment. Several children of a folder can share a subject,
+/// so every one of them is followed; the first note reached in tree order is
+/// kept, or with --all every one of them.
+fn resolve_path<'a>(opts: &Options, json: &'a Value, segments: &[&str], path: &mut Vec<usize>, results: &mut Vec<Match<'a>>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        results.push(Match { note: json, path: path.clone() });
+        return;
+    };
+    for (i, child) in opts.visited_children(note_children(json).map(Vec::as_slice).unwrap_or_default()) {
+        if !opts.all && !results.is_empty() {
+            return;
+        }
+        path.push(i);
+        // the roots of an array file have no subject, the path starts below them
+        if json.is_array() {
+            resolve_path(opts, child, segments, path, results);
+        } else if child["subject"].as_str() == Some(segment) {
+            resolve_path(opts, child, rest, path, results);
+        }
+        path.pop();
+    }
+}
+
+/// Whether the note is a folder, with children, whose subject or id is `name`.
+fn is_folder_named(json: &Value, name: &str) -> bool {
+    let is_folder = json["children"].as_array().is_some_and(|children| !children.is_empty());
+    is_folder && (json["subject"].as_str() == Some(name) || json["id"].as_str() == Some(name))
+}
+
+/// Find the first folder, in tree order, whose subject or id is `name` and
+/// leave the child positions leading to it in `path`. Folders excluded with
+/// --skip-folder are not looked into.
+fn find_folder(opts: &Options, json: &Value, name: &str, path: &mut Vec<usize>) -> bool {
+    for (i, child) in opts.visited_children(note_children(json).map(Vec::as_slice).unwrap_or_default()) {
+        path.push(i);
+        if is_folder_named(child, name) {
+            return true;
+        }
+        if find_folder(opts, child, name, path) {
+            return true;
+        }
+        path.pop();
+    }
+    false
+}
+
+/// The byte offsets of the matched values in the raw text, one per line.
+fn byte_offset_output(raw: Option<&str>, query: &Query, matches: &[Match]) -> Result<Option<String>, Box<dyn Error>> {
+    let raw = raw.ok_or("--byte-offset needs the notes file, it cannot be used in queries of --repl or --serve")?;
+    let field = match &query.field {
+        Field::Key(key) => key.as_str(),
+        _ => "content",
+    };
+    let offsets = byte_offsets(raw, field, matches);
+    Ok(if offsets.is_empty() { None } else { Some(offsets.join("\n")) })
+}
+
+/// Find where the matched values of `field` begin in the raw text the notes
+/// were parsed from. serde_json keeps no source positions, so each value is
+/// searched for as a json string the way serde_json writes it, starting after
+/// the value found for the previous match. This is only a heuristic: values
+/// written with other escapes, like \u00e9 for é, are not found and reported
+/// as unknown, and a value that also occurs earlier in the file than in its
+/// own note, e.g. as a subject, is reported at that earlier spot.
+fn byte_offsets(raw: &str, field: &str, matches: &[Match]) -> Vec<String> {
+    let mut from = 0;
+    matches.iter()
+        .map(|m| {
+            let Some(needle) = m.note[field].as_str().and_then(|value| serde_json::to_string(value).ok()) else {
+                return String::from("unknown");
+            };
+            match raw[from..].find(&needle) {
+                Some(at) => {
+                    let start = from + at;
+                    from = start + needle.len();
+                    // the value starts after the opening quote
+                    (start + 1).to_string()
+                },
+                None => String::from("unknown"),
+            }
+        })
+        .collect()
+}
+
+/// Count the notes in a subtree, not counting the folders themselves.
+fn count_notes(json: &Value, progress: &Progress) -> usize {
+    let mut count = 0;
+    let _: ControlFlow<()> = visit_notes(&Options::default(), progress, json, &mut Vec::new(), &mut |_, _| {
+        count += 1;
+        ControlFlow::Continue(())
+    });
+    count
+}
+
+/// The root note of the tree: the value at --root-path, or the whole file.
+fn notes_root<'a>(opts: &Options, json: &'a Value) -> Result<&'a Value, String> {
+    match &opts.root_path {
+        Some(root_path) => json.pointer(root_path).ok_or_else(|| format!("root path '{root_path}' does not exist")),
+        None => Ok(json),
+    }
+}
+
+/// Collect every note of the tree, without the folders, as a flat list. With
+/// --with-path each note gets a path field holding the subjects of its folders.
+fn flatten(json: &Value, opts: &Options, progress: &Progress) -> Vec<Value> {
+    // the roots are not notes, even without children
+    let roots = if json.is_array() { 1 } else { 0 };
+    let mut notes = Vec::new();
+    let _: ControlFlow<()> = visit_notes(opts, progress, json, &mut Vec::new(), &mut |note, path| {
+        if path.len() > roots && note_children(note).is_none() {
+            notes.push(flat_note(opts, json, note, path));
+        }
+        ControlFlow::Continue(())
+    });
+    notes
+}
+
+/// A note of --flatten, found at the child positions of `path` in `root`.
+fn flat_note(opts: &Options, root: &Value, json: &Value, path: &[usize]) -> Value {
+    let mut note = without_excluded(opts, json);
+    if let (true, Value::Object(fields)) = (opts.with_path, &mut note) {
+        let first = if root.is_array() { 2 } else { 1 };
+        let folders: Vec<String> = (first..path.len())
+            .filter_map(|depth| note_at(root, &path[..depth]))
+            .map(folder_name)
+            .collect();
+        fields.insert(String::from("path"), Value::String(folders.join("/")));
+    }
+    if let (true, Value::Object(fields)) = (opts.hash, &mut note) {
+        fields.insert(String::from("hash"), Value::String(note_hash(json)));
+    }
+    note
+}
+
+/// Collect the names of the fields of every object in the tree.
+fn collect_keys<'a>(json: &'a Value, progress: &Progress, keys: &mut BTreeSet<&'a str>) {
+    match json {
+        Value::Object(fields) => {
+            progress.tick();
+            for (key, value) in fields {
+                keys.insert(key);
+                collect_keys(value, progress, keys);
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|value| collect_keys(value, progress, keys)),
+        _ => (),
+    }
+}
+
+/// The notes directly under the root, or under each of the roots of a file
+/// holding an array of them.
+fn top_level_notes(json: &Value) -> Vec<&Value> {
+    let roots = if json.is_array() { note_children(json).into_iter().flatten().collect() } else { vec![json] };
+    roots.into_iter().flat_map(|root| note_children(root).into_iter().flatten()).collect()
+}
+
+/// The number of notes under each top-level folder, largest first.
+fn folder_counts(json: &Value, progress: &Progress) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = top_level_notes(json).into_iter()
+        .filter(|child| note_children(child).is_some())
+        .map(|folder| (note_label(folder), count_notes(folder, progress)))
+        .collect();
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
+
+/// The `n` notes with the longest content, as their label and the length of
+/// their content in characters, longest first. Notes of the same length are
+/// ordered by id.
+fn top_longest(json: &Value, progress: &Progress, n: usize) -> Vec<(String, usize)> {
+    let mut notes = Vec::new();
+    collect_lengths(json, progress, &mut notes);
+    if n < notes.len() {
+        // only the first n need to be in order
+        notes.select_nth_unstable(n);
+        notes.truncate(n);
+    }
+    notes.sort_unstable();
+    notes.into_iter().map(|(Reverse(len), _, label)| (label, len)).collect()
+}
+
+fn collect_lengths<'a>(json: &'a Value, progress: &Progress, notes: &mut Vec<(Reverse<usize>, &'a str, String)>) {
+    let _: ControlFlow<()> = visit_notes(&Options::default(), progress, json, &mut Vec::new(), &mut |note, _| {
+        // an empty array of roots holds no notes
+        if !note.is_array() {
+            let len = note["content"].as_str().map_or(0, |content| content.chars().count());
+            notes.push((Reverse(len), note["id"].as_str().unwrap_or_default(), note_label(note)));
+        }
+        ControlFlow::Continue(())
+    });
+}
+
+/// A note: a leaf of the tree.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Note {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// The other fields, e.g. the dates or an empty children array, as they
+    /// are in the file.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl Note {
+    /// The value of a string field, whether it has a field of its own or is
+    /// one of the extra ones.
+    pub fn field(&self, key: &str) -> Option<&str> {
+        match key {
+            "id" => self.id.as_deref(),
+            "subject" => self.subject.as_deref(),
+            "content" => self.content.as_deref(),
+            _ => self.extra.get(key).and_then(Value::as_str),
+        }
+    }
+}
+
+/// A folder holding notes and other folders.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Folder {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub children: Vec<Entry>,
+    /// The other fields as they are in the file.
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+/// An entry of a folder. Like the traversals of the command line tool, only
+/// a non-empty children array makes a folder, so an empty folder reads as a
+/// note whose extra fields hold the empty array.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Entry {
+    Folder(Folder),
+    Note(Note),
+}
+
+impl<'de> Deserialize<'de> for Entry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Entry, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let entry = match &value["children"] {
+            Value::Array(children) if !children.is_empty() => Folder::deserialize(value).map(Entry::Folder),
+            _ => Note::deserialize(value).map(Entry::Note),
+        };
+        entry.map_err(de::Error::custom)
+    }
+}
+
+/// The whole notes file: the tree under its root folder, or an array of
+/// trees.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum NotesFile {
+    Tree(Folder),
+    Trees(Vec<Folder>),
+}
+
+impl<'de> Deserialize<'de> for NotesFile {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<NotesFile, D::Error> {
+        // decided up front, so that errors point at the field that is wrong
+        // rather than saying that no variant matched
+        let value = Value::deserialize(deserializer)?;
+        let file = if value.is_array() {
+            Vec::deserialize(value).map(NotesFile::Trees)
+        } else {
+            Folder::deserialize(value).map(NotesFile::Tree)
+        };
+        file.map_err(de::Error::custom)
+    }
+}
+
+impl NotesFile {
+    /// The root folders of the trees in the file.
+    pub fn roots(&self) -> &[Folder] {
+        match self {
+            NotesFile::Tree(root) => std::slice::from_ref(root),
+            NotesFile::Trees(roots) => roots,
+        }
+    }
+}
+
+/// The folders leading from the root of a tree to a note, outermost first.
+/// The root folder of the file itself is left out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotePath<'a> {
+    folders: Vec<&'a Folder>,
+}
+
+impl<'a> NotePath<'a> {
+    pub fn folders(&self) -> &[&'a Folder] {
+        &self.folders
+    }
+
+    /// The number of folders above the note.
+    pub fn depth(&self) -> usize {
+        self.folders.len()
+    }
+}
+
+/// The subjects of the folders joined by `/`, as --show-path prints them.
+impl fmt::Display for NotePath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let subjects: Vec<&str> = self.folders.iter()
+            .map(|folder| folder.subject.as_deref().filter(|subject| !subject.is_empty()).unwrap_or("<untitled>"))
+            .collect();
+        write!(f, "{}", subjects.join("/"))
+    }
+}
+
+/// The notes of a tree in depth-first order, see `NotesTree::iter`.
+pub struct Iter<'a> {
+    roots: std::slice::Iter<'a, Folder>,
+    /// The folders being walked, each with its entries left to visit.
+    stack: Vec<(&'a Folder, std::slice::Iter<'a, Entry>)>,
+}
+
+impl<'a> Iter<'a> {
+    fn path(&self) -> NotePath<'a> {
+        NotePath { folders: self.stack.iter().skip(1).map(|&(folder, _)| folder).collect() }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Note;
+
+    fn next(&mut self) -> Option<&'a Note> {
+        loop {
+            let Some((_, entries)) = self.stack.last_mut() else {
+                let root = self.roots.next()?;
+                self.stack.push((root, root.children.iter()));
+                continue;
+            };
+            match entries.next() {
+                Some(Entry::Note(note)) => return Some(note),
+                Some(Entry::Folder(folder)) => self.stack.push((folder, folder.children.iter())),
+                None => {
+                    self.stack.pop();
+                },
+            }
+        }
+    }
+}
+
+/// The notes of a tree with the folders leading to them, see
+/// `NotesTree::iter_with_path`.
+pub struct IterWithPath<'a>(Iter<'a>);
+
+impl<'a> Iterator for IterWithPath<'a> {
+    type Item = (&'a Note, NotePath<'a>);
+
+    fn next(&mut self) -> Option<(&'a Note, NotePath<'a>)> {
+        let note = self.0.next()?;
+        Some((note, self.0.path()))
+    }
+}
+
+/// A parsed notes file.
+pub struct NotesTree {
+    file: NotesFile,
+}
+
+impl NotesTree {
+    /// Parse the notes from the json of a notes file, e.g. an open
+    /// `fs::File`. Files whose fields do not have the types of the model
+    /// fail here, e.g. a subject that is not a string.
+    pub fn from_reader<R: Read>(reader: R) -> Result<NotesTree, serde_json::Error> {
+        Ok(NotesTree { file: serde_json::from_reader(reader)? })
+    }
+
+    /// The parsed file.
+    pub fn file(&self) -> &NotesFile {
+        &self.file
+    }
+
+    /// The first note, in tree order, whose field `key` has the value `val`.
+    pub fn find(&self, key: &str, val: &str) -> Option<&Note> {
+        self.iter().find(|note| note.field(key) == Some(val))
+    }
+
+    /// Every note of the tree, folders left out, in depth-first order: the
+    /// order in which the tool searches them.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { roots: self.file.roots().iter(), stack: Vec::new() }
+    }
+
+    /// Like `iter`, along with the folders leading to each note.
+    pub fn iter_with_path(&self) -> IterWithPath<'_> {
+        IterWithPath(self.iter())
+    }
+
+    /// Call `f` on every note, in the order of `iter_with_path`, until it
+    /// breaks. Returns the value it broke with, if it did.
+    pub fn visit<B>(&self, mut f: impl FnMut(&Note, &NotePath) -> ControlFlow<B>) -> ControlFlow<B> {
+        for (note, path) in self.iter_with_path() {
+            f(note, &path)?;
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// The outline of the tree printed when the tool is given no query: the
+    /// ids of the notes with the start of their subject and content.
+    pub fn summary(&self) -> Value {
+        file_summary(&self.file, &Options::default(), &Progress::new(false))
+    }
+}
+
+/// Run the command line tool on the arguments of the process and return its
+/// exit code.
+pub fn run() -> Result<ExitCode, Box<dyn Error>> {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{} {message}", label(None, "error"));
+            return Ok(ExitCode::from(2));
+        },
+    };
+    let args: Vec<String> = env::args().collect();
+    let error_format = error_format_arg(&args);
+    let args = parse_args(args.into_iter(), env::var(NOTES_FILE_VAR).ok().or_else(|| config.notes_file()));
+    let (key, val, input, contains, mut opts) = match args {
+        Args::Help(help) | Args::Version(help) => {
+            print!("{help}");
+            return Ok(ExitCode::SUCCESS);
+        },
+        Args::Completions(shell) => {
+            clap_complete::generate(shell, &mut command(), "vivaldi_notes_parser", &mut io::stdout());
+            return Ok(ExitCode::SUCCESS);
+        },
+        Args::BadArgs(reason) if matches!(error_format, ErrorFormat::Json) => {
+            eprintln!("{}", format_error(error_format, Failure::Args, &reason, None).unwrap_or_default());
+            return Ok(Failure::Args.exit_code());
+        },
+        Args::BadArgs(reason) => {
+            eprintln!("{} {reason}", label(config.color, "error"));
+            usage(&mut io::stderr())?;
+            return Ok(ExitCode::from(2));
+        },
+        Args::Key {key, val, input, contains, opts} => (key, val, input, contains, opts),
+    };
+    config.apply(&mut opts);
+
+    let list_name = input.file_name().map(String::from);
+    let inputs = match input.expand() {
+        Ok(inputs) => inputs,
+        Err(message) => return Ok(fail(&opts, Failure::Io, &message, list_name.as_deref())),
+    };
+
+    if opts.require_all_files {
+        for input in &inputs {
+            if let Err(message) = input.check() {
+                return Ok(fail(&opts, Failure::Io, &message, input.file_name()));
+            }
+        }
+    }
+
+    let mut found = false;
+    // arrays and outlines of all the inputs, printed as one document at the end
+    let mut document: Option<Found> = None;
+    let mut skipped = Vec::new();
+    let mut timing = Timing::default();
+    let mut out = Output::new(opts.pager, opts.separator.clone());
+    let root_path = opts.root_path.clone();
+    for input in &inputs {
+        let (mut notes_json, raw) = match input.load(&opts, &mut timing) {
+            Ok(loaded) => loaded,
+            Err((failure, message)) if opts.keep_going => {
+                warn(&opts, failure, &format!("skipping input: {message}"), input.file_name());
+                skipped.push(failure);
+                continue;
+            },
+            Err((failure, message)) => return Ok(fail(&opts, failure, &message, input.file_name())),
+        };
+        let schema = adapt_schema(&opts, &mut notes_json);
+
+        // --root extends the root path, which differs between the inputs
+        if let Some(folder) = &opts.root_folder {
+            let base = root_path.as_deref().unwrap_or_default();
+            let mut path = Vec::new();
+            match notes_json.pointer(base) {
+                Some(json) if find_folder(&opts, json, folder, &mut path) => {
+                    opts.root_path = Some(format!("{base}{}", json_pointer(json, &path)));
+                },
+                _ => {
+                    let message = format!("no folder '{folder}' for --root in {}", input.file_name().unwrap_or("stdin"));
+                    warn(&opts, Failure::NotFound, &message, input.file_name());
+                    continue;
+                },
+            }
+        }
+
+        if let Some(other) = &opts.merge {
+            let mut other_json = match Input::File(String::from(other)).load(&opts, &mut timing) {
+                Ok((other_json, _)) => other_json,
+                Err((failure, message)) => return Ok(fail(&opts, failure, &message, Some(other))),
+            };
+            adapt_schema(&opts, &mut other_json);
+            if let Some(merged) = serialize(&opts, &merge_notes(&notes_json, &other_json)) {
+                out.print(&merged);
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        if let Some((from, to)) = &opts.replace {
+            let mut notes_json = notes_json;
+            let Some(root) = notes_json.pointer_mut(opts.root_path.as_deref().unwrap_or_default()) else {
+                return Ok(fail(&opts, Failure::Parse, "root path does not exist", input.file_name()));
+            };
+            let Some(query) = Query::from_args(key.clone(), val.clone(), contains.clone(), &opts) else {
+                return Ok(fail(&opts, Failure::NotFound, "no query to replace in", input.file_name()));
+            };
+            let count = replace_in_notes(&query, &opts, root, from, to);
+            // the file is written back with the field names it was read with
+            let renames: Vec<(&str, &str)> = schema.renames().iter().map(|&(old, new)| (new, old)).collect();
+            rename_fields(&mut notes_json, &renames);
+            let target = opts.output.as_deref().or(input.file_name()).unwrap_or_default();
+            if opts.dry_run {
+                out.print(&format!("{count} replacements would be written to {target}"));
+            } else if count > 0 || opts.output.is_some() {
+                fs::write(target, serde_json::to_string_pretty(&notes_json)?)
+                    .map_err(|e| format!("could not write '{target}': {e}"))?;
+                out.print(&format!("{count} replacements written to {target}"));
+            }
+            if count == 0 {
+                return Ok(fail(&opts, Failure::NotFound, "no text to replace found", input.file_name()));
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let root = match notes_root(&opts, &notes_json) {
+            Ok(root) => root,
+            Err(message) => return Ok(fail(&opts, Failure::Parse, &message, input.file_name())),
+        };
+
+        if opts.repl {
+            repl(&opts, root)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        #[cfg(unix)]
+        if let Some(socket) = &opts.serve {
+            serve(&opts, socket, root)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let start = Instant::now();
+        let output = find_output(key.clone(), &val, &contains, &opts, root, raw.as_deref())?;
+        timing.traversal += start.elapsed();
+        match (output, &mut document) {
+            (None, _) => continue,
+            (Some(Found::Text(text)), _) => print_output(&mut out, &opts, &text),
+            (Some(found), Some(document)) => document.extend(found),
+            (Some(found), None) => document = Some(found),
+        }
+        found = true;
+    }
+    if let Some(text) = document.and_then(|document| document.render(&opts)) {
+        print_output(&mut out, &opts, &text);
+    }
+
+    if opts.timing {
+        timing.report();
+    }
+    if let Some(&failure) = skipped.last() {
+        return Ok(if skipped.len() == inputs.len() {
+            fail(&opts, failure, "none of the inputs could be loaded", list_name.as_deref())
+        } else {
+            let message = format!("skipped {} of {} inputs", skipped.len(), inputs.len());
+            fail(&opts, Failure::Partial, &message, list_name.as_deref())
+        });
+    }
+    if found {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(fail(&opts, Failure::NotFound, "no matching note found", list_name.as_deref()))
+    }
+}
+
+/// Unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_string_iter<'a>(v: &'a Vec<&'a str>) -> Box<dyn Iterator<Item = String> + 'a>
+    {
+        Box::new(v.iter().map(|&i| String::from(i)))
+    }
+
+    fn query(key: &str, val: Option<&str>, contains: Option<&str>) -> Query
+    {
+        Query {
+            field: Field::Key(String::from(key)),
+            val: val.map(String::from),
+            contains: contains.map(String::from),
+        }
+    }
+
+    /// What would be printed for the first match of the query.
+    fn find_first(query: &Query, opts: &Options, notes: &Value) -> Option<String>
+    {
+        traverse_json(query, opts, &Progress::new(false), notes, &mut Vec::new())
+            .and_then(|m| match_output(opts, &m))
+    }
+
+    /// What would be printed for every match of the query.
+    fn find_all(query: &Query, opts: &Options, notes: &Value) -> Vec<String>
+    {
+        let mut matches = Vec::new();
+        traverse_json_all(query, opts, &Progress::new(false), notes, &mut Vec::new(), &mut matches);
+        matches.iter().filter_map(|m| match_output(opts, m)).collect()
+    }
+
+    #[test]
+    fn test_index()
+    {
+        let s = "abcdefg";
+        let t = &s[..std::cmp::min(10, s.len())];
+        println!("{t}");
+    }
+
+    #[test]
+    fn test_parse_duration()
+    {
+        assert_eq!(parse_duration("30m"), Some(30 * 60 * 1_000_000));
+        assert_eq!(parse_duration("2h"), Some(2 * 60 * 60 * 1_000_000));
+        assert_eq!(parse_duration("1d12h"), Some(36 * 60 * 60 * 1_000_000));
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("7w"), None);
+    }
+
+    #[test]
+    fn test_parse_iso_date()
+    {
+        assert_eq!(parse_iso_date("1601-01-01"), Some(0));
+        assert_eq!(parse_iso_date("1970-01-01T00:00:00Z"), Some(CHROME_EPOCH_OFFSET_MICROS));
+        assert_eq!(parse_iso_date("2024-01-01"), Some(1_704_067_200 * 1_000_000 + CHROME_EPOCH_OFFSET_MICROS));
+        assert_eq!(parse_iso_date("2024-02-29T08:30"), Some((1_709_164_800 + 8 * 3600 + 30 * 60) * 1_000_000 + CHROME_EPOCH_OFFSET_MICROS));
+        assert_eq!(parse_iso_date("2024-03-01 00:00:01"), Some(1_709_251_201 * 1_000_000 + CHROME_EPOCH_OFFSET_MICROS));
+        for invalid in ["", "2024", "2024-1-1", "2023-02-29", "2024-13-01", "2024-01-32", "2024-01-01T24:00", "2024-01-01T08", "01/02/2024"] {
+            assert_eq!(parse_iso_date(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_added_range()
+    {
+        let added = |date: &str| Value::String(parse_iso_date(date).unwrap().to_string());
+        let notes = json!({"children": [
+            {"id": "1", "date_added": added("2023-12-31T23:59:59")},
+            {"id": "2", "date_added": added("2024-01-01")},
+            {"id": "3", "date_added": added("2024-01-31T12:00")},
+            {"id": "4", "date_added": added("2024-02-01")},
+            {"id": "5"},
+            {"id": "6", "children": [{"id": "7", "date_added": added("2024-01-15")}]},
+        ]});
+        let args = vec!["V", "--has", "id", "--after", "2024-01-01", "--before", "2024-02-01"];
+        let Args::Key { key, val, contains, mut opts, .. } = parse_args(get_string_iter(&args), None) else {
+            panic!("expected Args::Key");
+        };
+        opts.print_key = Some(String::from("id"));
+        let query = Query::from_args(key, val, contains, &opts).unwrap();
+        assert_eq!(find_all(&query, &opts, &notes), vec!["2", "3", "7"]);
+
+        let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+        let ids: Vec<&Value> = summary["children"].as_array().unwrap().iter().map(|child| &child["id"]).collect();
+        assert_eq!(ids, vec!["2", "3", "6"]);
+
+        let args = vec!["V", "--after", "yesterday"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_modified_since()
+    {
+        let opts = Options { modified_since: Some(100), ..Default::default() };
+        assert!(passes_filters(&opts, &json!({"date_added": "50", "date_modified": "150"})));
+        assert!(passes_filters(&opts, &json!({"date_added": 120})));
+        assert!(!passes_filters(&opts, &json!({"date_added": "50"})));
+        assert!(!passes_filters(&opts, &json!({"content": "no dates"})));
+        assert!(passes_filters(&Options::default(), &json!({"content": "no dates"})));
+    }
+
+    #[test]
+    fn test_mismatch_reason()
+    {
+        let opts = Options::default();
+        let note = json!({"subject": "Todo", "content": "milk", "id": 7});
+        assert_eq!(mismatch_reason(&query("url", Some("x"), None), &opts, &note), "field 'url' is missing");
+        assert_eq!(mismatch_reason(&query("subject", Some("Done"), None), &opts, &note), "field 'subject' has a different value");
+        assert_eq!(mismatch_reason(&query("content", None, Some("eggs")), &opts, &note), "field 'content' does not contain the text");
+        assert_eq!(mismatch_reason(&query("id", Some("7"), None), &opts, &note), "field 'id' is not a string");
+        let opts = Options { has: Some(String::from("url")), ..Default::default() };
+        assert_eq!(mismatch_reason(&query("subject", Some("Todo"), None), &opts, &note), "field 'url' for --has is missing");
+        let opts = Options { invert: true, ..Default::default() };
+        assert_eq!(mismatch_reason(&query("subject", Some("Todo"), None), &opts, &note), "excluded by --invert");
+    }
+
+    #[test]
+    fn test_has()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "content": "Todo", "attachmentId": "a1"},
+            {"id": "2", "content": "Todo", "attachmentId": null},
+            {"id": "3", "content": "done", "attachmentId": "a3"},
+        ]});
+        let opts = Options { has: Some(String::from("attachmentId")), print_key: Some(String::from("id")), ..Default::default() };
+        let only_has = Query::from_args(None, None, None, &opts).unwrap();
+        assert_eq!(find_all(&only_has, &opts, &notes), vec!["1", "3"]);
+        assert_eq!(find_all(&query("content", Some("Todo"), None), &opts, &notes), vec!["1"]);
+    }
+
+    #[test]
+    fn test_several_keys()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo", "type": "note"},
+            {"id": "2", "subject": "Todo", "type": "folder"},
+            {"id": "3", "subject": "Done", "type": "note"},
+        ]});
+        let args = vec!["V", "-k", "subject", "-v", "Todo", "-k", "type", "-v", "note"];
+        let Args::Key { key, val, contains, mut opts, .. } = parse_args(get_string_iter(&args), None) else {
+            panic!("expected Args::Key");
+        };
+        opts.print_key = Some(String::from("id"));
+        let query = Query::from_args(key, val, contains, &opts).unwrap();
+        assert_eq!(find_all(&query, &opts, &notes), vec!["1"]);
+        assert_eq!(mismatch_reason(&query, &opts, &notes["children"][1]), "field 'type' has a different value");
+
+        // a -v or -c before any -k belongs to the first one
+        let args = vec!["V", "-c", "od", "-k", "subject", "-k", "type", "-c", "no"];
+        let Args::Key { key, val, contains, mut opts, .. } = parse_args(get_string_iter(&args), None) else {
+            panic!("expected Args::Key");
+        };
+        assert_eq!((key.as_deref(), contains.as_deref()), (Some("subject"), Some("od")));
+        opts.print_key = Some(String::from("id"));
+        let query = Query::from_args(key, val, contains, &opts).unwrap();
+        assert_eq!(find_all(&query, &opts, &notes), vec!["1"]);
+
+        let args = vec!["V", "-k", "subject", "-v", "Todo", "-k", "type"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+        let args = vec!["V", "-k", "subject", "-v", "Todo", "-v", "Done"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+        let args = vec!["V", "-k", "priority", "--min", "1", "-k", "type", "-v", "note"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_not()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo draft", "type": "note"},
+            {"id": "2", "subject": "Todo", "type": "note"},
+            {"id": "3", "subject": "Done", "type": "folder"},
+        ]});
+        let find = |args: &[&str]| {
+            let args: Vec<&str> = std::iter::once("V").chain(args.iter().copied()).collect();
+            let Args::Key { key, val, contains, mut opts, .. } = parse_args(get_string_iter(&args), None) else {
+                panic!("expected Args::Key for {:?}", args);
+            };
+            opts.print_key = Some(String::from("id"));
+            let query = Query::from_args(key, val, contains, &opts).unwrap();
+            find_all(&query, &opts, &notes)
+        };
+        assert_eq!(find(&["-k", "subject", "-c", "draft", "--not"]), vec!["2", "3"]);
+        // --not only negates the -k it follows
+        assert_eq!(find(&["-k", "type", "-v", "note", "-k", "subject", "-c", "draft", "--not"]), vec!["2"]);
+        assert_eq!(find(&["-k", "type", "-v", "note", "--not", "-k", "subject", "-c", "o"]), vec!["3"]);
+        assert_eq!(find(&["--any-field", "-c", "Todo", "--not"]), vec!["3"]);
+
+        let args = vec!["V", "-k", "type", "-v", "note", "-k", "subject", "-c", "draft", "--not"];
+        let Args::Key { key, val, contains, opts, .. } = parse_args(get_string_iter(&args), None) else {
+            panic!("expected Args::Key");
+        };
+        let query = Query::from_args(key, val, contains, &opts).unwrap();
+        assert_eq!(mismatch_reason(&query, &opts, &notes["children"][0]), "field 'subject' matches, excluded by --not");
+        let args = vec!["V", "--has", "type", "--not"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_query_expression()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo", "content": "call the bank, urgent"},
+            {"id": "2", "subject": "Todo", "content": "answer asap"},
+            {"id": "3", "subject": "Todo", "content": "whenever"},
+            {"id": "4", "subject": "Done", "content": "urgent"},
+        ]});
+        let find = |expr: &str| {
+            let args = vec!["V", "--query", expr];
+            let Args::Key { key, val, contains, mut opts, .. } = parse_args(get_string_iter(&args), None) else {
+                panic!("expected Args::Key for {}", expr);
+            };
+            opts.print_key = Some(String::from("id"));
+            let query = Query::from_args(key, val, contains, &opts).unwrap();
+            find_all(&query, &opts, &notes)
+        };
+        assert_eq!(find(r#"subject=="Todo" && (content~"urgent" || content~"asap")"#), vec!["1", "2"]);
+        assert_eq!(find("subject==Todo and not content~urgent"), vec!["2", "3"]);
+        assert_eq!(find("content~urgent || id==3 && subject!=Todo"), vec!["1", "4"]);
+        assert_eq!(find("!(subject==Todo) OR content!~e"), vec!["4"]);
+
+        assert_eq!(parse_expr(r#"subject=="say \"hi\"""#), Ok(Expr::Field(Predicate {
+            key: String::from("subject"),
+            val: Some(String::from("say \"hi\"")),
+            contains: None,
+            negated: false,
+        })));
+        for invalid in ["subject", "subject==", "(subject==a", "subject==a)", "subject==\"a", "&& subject==a"] {
+            assert!(parse_expr(invalid).is_err(), "{}", invalid);
+        }
+        let args = vec!["V", "--query", "subject==a", "-k", "id"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_numeric_range()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "priority": 1},
+            {"id": "2", "priority": 2.5},
+            {"id": "3", "priority": 5},
+            {"id": "4", "priority": "3"},
+            {"id": "5"},
+        ]});
+        let opts = Options { min_value: Some(2.0), max_value: Some(5.0), print_key: Some(String::from("id")), ..Default::default() };
+        let priority = query("priority", None, None);
+        assert_eq!(find_all(&priority, &opts, &notes), vec!["2", "3"]);
+        let opts = Options { max_value: None, ..opts };
+        assert_eq!(find_all(&priority, &opts, &notes), vec!["2", "3"]);
+        assert_eq!(mismatch_reason(&priority, &opts, &notes["children"][3]), "field 'priority' is not a number");
+        assert_eq!(mismatch_reason(&priority, &opts, &notes["children"][0]), "field 'priority' is out of range");
+    }
+
+    #[test]
+    fn test_content_length()
+    {
+        let opts = Options { min_len: Some(2), max_len: Some(3), ..Default::default() };
+        assert!(passes_filters(&opts, &json!({"content": "äöü"})));
+        assert!(!passes_filters(&opts, &json!({"content": "a"})));
+        assert!(!passes_filters(&opts, &json!({"content": "abcd"})));
+        let opts = Options { max_len: Some(0), ..Default::default() };
+        assert!(passes_filters(&opts, &json!({"subject": "no content"})));
+
+        // without -k the filters select the notes on their own instead of falling back to the summary
+        let notes = json!({"children": [{"id": "1", "content": "a"}, {"id": "2", "content": "abcd"}]});
+        let search = |args: Vec<&str>| {
+            let Args::Key { key, val, contains, opts, .. } = parse_args(get_string_iter(&args), None) else {
+                panic!("expected Args::Key");
+            };
+            run_query(key, &val, &contains, &opts, &notes, None).unwrap()
+        };
+        assert_eq!(search(vec!["V", "--min-len", "2", "--all"]), Some(String::from("abcd")));
+        assert_eq!(search(vec!["V", "--max-len", "0"]), None);
+        assert_eq!(search(vec!["V", "--since", "1d"]), None);
+        assert_eq!(search(vec!["V", "--min-len", "2", "--invert", "--print-key", "id"]), Some(String::from("1")));
+        assert!(matches!(parse_args(get_string_iter(&vec!["V", "--invert"]), None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_format_json_content()
+    {
+        assert_eq!(format_json_content(String::from("{\"a\":[1,2]}")), "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+        assert_eq!(format_json_content(String::from("{not json")), "{not json");
+        assert_eq!(format_json_content(String::from("42")), "42");
+        let note = json!({"content": "[ ]"});
+        let opts = Options { format_content: true, ..Default::default() };
+        assert_eq!(note_output(&opts, &note), Some(String::from("[]")));
+    }
+
+    #[test]
+    fn test_escape()
+    {
+        assert_eq!(escape("a\nb\tc\\d"), "a\\nb\\tc\\\\d");
+        assert_eq!(unescape(&escape("line 1\n\tline 2 \\n")), "line 1\n\tline 2 \\n");
+    }
+
+    #[test]
+    fn test_unescape()
+    {
+        assert_eq!(unescape("a\\nb\\tc\\0"), "a\nb\tc\0");
+        assert_eq!(unescape("\\\\n"), "\\n");
+        assert_eq!(unescape("---"), "---");
+        assert_eq!(unescape("\\x\\"), "\\x\\");
+    }
+
+    #[test]
+    fn test_unique()
+    {
+        let notes = json!({"children": [
+            {"content": "Todo b"},
+            {"content": "Todo a"},
+            {"children": [{"content": "Todo b"}]},
+            {"content": "Todo a"},
+            {"content": "Todo c"},
+        ]});
+        let search = |opts: &Options| run_query(Some(String::from("content")), &None, &Some(String::from("Todo")), opts, &notes, None).unwrap();
+        let opts = Options { all: true, unique: true, ..Default::default() };
+        assert_eq!(search(&opts).as_deref(), Some("Todo b\nTodo a\nTodo c"));
+        let opts = Options { all: true, ..Default::default() };
+        assert_eq!(search(&opts).as_deref(), Some("Todo b\nTodo a\nTodo b\nTodo a\nTodo c"));
+    }
+
+    #[test]
+    fn test_separator_join()
+    {
+        let notes = json!({"children": [{"content": "Todo a"}, {"content": "done"}, {"content": "Todo b"}]});
+        let opts = Options { all: true, separator: Some(String::from("\0")), ..Default::default() };
+        let output = run_query(Some(String::from("content")), &None, &Some(String::from("Todo")), &opts, &notes, None).unwrap();
+        assert_eq!(output.as_deref(), Some("Todo a\0Todo b"));
+
+        // the outputs of the inputs are joined the same way, without a newline at the end
+        let mut out = Output { pager: Pager::Never, collected: Some(String::new()), separator: opts.separator.clone(), printed: false };
+        out.print(&output.unwrap());
+        out.print("Todo c");
+        assert_eq!(out.collected.take().as_deref(), Some("Todo a\0Todo b\0Todo c"));
+        let mut out = Output { pager: Pager::Never, collected: Some(String::new()), separator: None, printed: false };
+        out.print("Todo a");
+        out.print("Todo b");
+        assert_eq!(out.collected.take().as_deref(), Some("Todo a\nTodo b\n"));
+    }
+
+    #[test]
+    fn test_traverse_json_all()
+    {
+        let notes = json!({"children": [
+            {"subject": "Todo", "content": "first"},
+            {"children": [{"subject": "Todo list", "content": "second"}]},
+            {"subject": "Done", "content": "third"},
+        ]});
+        let contents = find_all(&query("subject", None, Some("Todo")), &Options::default(), &notes);
+        assert_eq!(contents, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn test_match_limit_per_file()
+    {
+        let notes = json!({"children": [{"content": "Todo 1"}, {"content": "Todo 2"}, {"content": "Todo 3"}]});
+        let opts = Options { all: true, match_limit_per_file: Some(2), ..Default::default() };
+        let output = run_query(Some(String::from("content")), &None, &Some(String::from("Todo")), &opts, &notes, None).unwrap();
+        assert_eq!(output, Some(String::from("Todo 1\nTodo 2")));
+    }
+
+    #[test]
+    fn test_occurrences()
+    {
+        let notes = json!({"children": [{"content": "rust and more rust"}, {"content": "no"}, {"content": "rustrustrust"}]});
+        let opts = Options { all: true, occurrences: true, ..Default::default() };
+        let output = run_query(Some(String::from("content")), &None, &Some(String::from("rust")), &opts, &notes, None).unwrap();
+        assert_eq!(output, Some(String::from("2\trust and more rust\n3\trustrustrust")));
+    }
+
+    #[test]
+    fn test_normalize_json()
+    {
+        let notes: Value = serde_json::from_str(r#"{"subject": "b", "children": [{"id": "2", "content": "x"}], "id": "1"}"#).unwrap();
+        let normalized = serde_json::to_string(&normalize_json(&notes)).unwrap();
+        assert_eq!(normalized, r#"{"children":[{"content":"x","id":"2"}],"id":"1","subject":"b"}"#);
+        assert_eq!(normalize_json(&notes), notes);
+    }
+
+    #[test]
+    fn test_branching_traversal()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let mut branching = Branching::default();
+        branching_traversal(&notes, &Progress::new(false), &mut branching);
+        assert_eq!(branching.folders, 2);
+        assert_eq!(branching.max_children, 3);
+        assert_eq!(branching.average(), 2.0);
+        assert_eq!(branching.widest_id, None);
+    }
+
+    #[test]
+    fn test_any_field()
+    {
+        let note = json!({"id": "7", "subject": "Shopping", "content": "milk"});
+        let opts = Options::default();
+        let any = |val: Option<&str>, contains: Option<&str>| Query {
+            field: Field::Any,
+            val: val.map(String::from),
+            contains: contains.map(String::from),
+        };
+        assert!(note_matches(&any(None, Some("milk")), &opts, &note));
+        assert!(!note_matches(&query("subject", None, Some("milk")), &opts, &note));
+        assert!(note_matches(&any(Some("7"), None), &opts, &note));
+    }
+
+    #[test]
+    fn test_combined_field()
+    {
+        let note = json!({"subject": "Shopping", "content": "list: milk"});
+        let opts = Options::default();
+        let field_query = |field| Query { field, val: None, contains: Some(String::from("Shopping\nlist")) };
+        assert!(note_matches(&field_query(Field::Combined), &opts, &note));
+        assert!(!note_matches(&field_query(Field::Any), &opts, &note));
+        let no_subject = json!({"content": "milk"});
+        let query = Query { field: Field::Combined, val: Some(String::from("\nmilk")), contains: None };
+        assert!(note_matches(&query, &opts, &no_subject));
+    }
+
+    #[test]
+    fn test_relevance()
+    {
+        let in_subject = json!({"subject": "Todo", "content": "nothing"});
+        let in_content = json!({"subject": "List", "content": "todo: Todo, Todo"});
+        let both = json!({"subject": "Todo", "content": "Todo"});
+        let opts = Options::default();
+        assert_eq!(relevance(&opts, &in_subject, "Todo"), 10);
+        assert_eq!(relevance(&opts, &in_content, "Todo"), 2);
+        assert_eq!(relevance(&opts, &both, "Todo"), 11);
+        assert_eq!(relevance(&opts, &both, ""), 0);
+        assert_eq!(relevance(&opts, &json!({}), "Todo"), 0);
+
+        let opts = Options { ignore_case: true, ..Default::default() };
+        assert_eq!(relevance(&opts, &in_content, "todo"), 3);
+        assert_eq!(relevance(&opts, &both, "TODO"), 11);
+    }
+
+    #[test]
+    fn test_array_root()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test_array.json").unwrap()).unwrap();
+        let content = find_first(&query("subject", Some("Todo queue"), None), &Options::default(), &notes);
+        assert_eq!(content, Some(String::from("second root")));
+
+        let summary = summary_value(&notes, &Options::default(), &Progress::new(false)).unwrap();
+        assert_eq!(summary[0]["children"][0]["id"], "1");
+        assert_eq!(summary[1]["children"][0]["subject"], "Todo queue");
+    }
+
+    #[test]
+    fn test_invert()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let opts = Options { invert: true, ..Default::default() };
+        let contents = find_all(&query("id", Some("1"), None), &opts, &notes);
+        assert_eq!(contents, vec!["test", "\\----- Test"]);
+    }
+
+    #[test]
+    fn test_pointer()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let found = notes.pointer("/children/1/children/0/content").and_then(|value| pointer_output(&Options::default(), value));
+        assert_eq!(found, Some(String::from("test")));
+        let found = notes.pointer("/children/0/children").and_then(|value| pointer_output(&Options::default(), value));
+        assert_eq!(found, Some(String::from("[]")));
+        assert_eq!(notes.pointer("/children/7"), None);
+        assert_eq!(notes.pointer("children"), None);
+    }
+
+    #[test]
+    fn test_split_query()
+    {
+        let words = split_query("-k subject -c \"Todo queue\"").unwrap();
+        assert_eq!(words, vec!["-k", "subject", "-c", "Todo queue"]);
+        let words = split_query("k subject c Todo").unwrap();
+        assert_eq!(words, vec!["-k", "subject", "-c", "Todo"]);
+        let words = split_query("  k id  v \"\" ").unwrap();
+        assert_eq!(words, vec!["-k", "id", "-v", ""]);
+        assert_eq!(split_query("-k subject -c \"Todo"), None);
+    }
+
+    #[test]
+    fn test_input_check()
+    {
+        assert!(Input::File(String::from("test.json")).check().is_ok());
+        let missing = Input::File(String::from("missing.json")).check();
+        assert_eq!(missing, Err(String::from("could not read 'missing.json': file not found")));
+        assert!(Input::Zip { archive: String::from("missing.zip"), entry: None }.check().is_err());
+        assert!(Input::Stdin.check().is_ok());
+    }
+
+    #[test]
+    fn test_answer_query()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        assert_eq!(answer_query("k id v 3", &notes), Ok(Some(String::from("test"))));
+        assert_eq!(answer_query("k subject v Missing", &notes), Ok(None));
+        assert!(answer_query("bogus", &notes).is_err());
+        assert!(answer_query("k subject c \"Todo", &notes).is_err());
+
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [{"id": "1", "content": "Todo a\nTodo b"}]},
+            {"id": "2", "content": "Todo c"},
+        ]});
+        assert_eq!(answer_query("-k content -c Todo", &notes), Ok(Some(String::from("Todo a\nTodo b"))));
+        assert_eq!(answer_query("-k id -v 2 --root Work", &notes), Ok(None));
+        assert_eq!(answer_query("-k content -c Todo --root Work --print-key id", &notes), Ok(Some(String::from("1"))));
+        assert_eq!(answer_query("-k id -v 1 --root Nope", &notes), Err(String::from("no folder 'Nope' for --root")));
+        assert_eq!(answer_query("-k id -v 1 --numbered", &notes), Ok(Some(String::from("     1\tTodo a\n     2\tTodo b"))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_answer_connection()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let (mut client, server) = UnixStream::pair().unwrap();
+        writeln!(client, "k id c 3").unwrap();
+        answer_connection(server, &notes).unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert_eq!(response, "test\n");
+    }
+
+    #[test]
+    fn test_truncate()
+    {
+        assert_eq!(truncate("abcdefg", 10), "abcdefg");
+        assert_eq!(truncate("abcdefg", 3), "abc");
+        assert_eq!(truncate("", 3), "");
+        // the 30th byte falls in the middle of the emoji and the accented e
+        let subject = format!("{}\u{1F600} tail", "a".repeat(28));
+        assert_eq!(truncate(&subject, 29), format!("{}\u{1F600}", "a".repeat(28)));
+        let subject = format!("{}\u{e9}t\u{e9}", "a".repeat(29));
+        assert_eq!(truncate(&subject, 30), format!("{}\u{e9}", "a".repeat(29)));
+
+        let summary = summary_value(&json!({"subject": subject}), &Options::default(), &Progress::new(false)).unwrap();
+        assert_eq!(summary["subject"], format!("{}\u{e9}\u{2026}", "a".repeat(29)));
+
+        // grapheme clusters of several code points are kept whole
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(truncate(&format!("{family}{family}"), 1), family);
+        assert_eq!(truncate("\u{1F1EF}\u{1F1F5}\u{1F1EB}\u{1F1F7}", 1), "\u{1F1EF}\u{1F1F5}");
+        assert_eq!(truncate("e\u{301}te\u{301}", 2), "e\u{301}t");
+        assert_eq!(truncate("\u{65E5}\u{672C}\u{8A9E}\u{306E}\u{30CE}\u{30FC}\u{30C8}", 3), "\u{65E5}\u{672C}\u{8A9E}");
+    }
+
+    #[test]
+    fn test_preview()
+    {
+        assert_eq!(preview("\u{65E5}\u{672C}\u{8A9E}\u{306E}\u{30CE}\u{30FC}\u{30C8}", Some(3)), "\u{65E5}\u{672C}\u{8A9E}\u{2026}");
+        assert_eq!(preview("\u{1F600}\u{1F600}", Some(1)), "\u{1F600}\u{2026}");
+        // nothing is cut, so there is no ellipsis
+        assert!(matches!(preview("\u{1F600}\u{1F600}", Some(2)), Cow::Borrowed("\u{1F600}\u{1F600}")));
+        assert_eq!(preview("short", None), "short");
+        assert_eq!(preview(&"x".repeat(40), Some(0)), "x".repeat(40));
+    }
+
+    #[test]
+    fn test_json_output()
+    {
+        let note = json!({"id": "7", "subject": "Todo", "date_added": "13300000000000000", "content": "milk", "children": []});
+        let opts = Options { json: true, ..Default::default() };
+        assert_eq!(note_output(&opts, &note), serde_json::to_string_pretty(&note).ok());
+        let opts = Options { json: true, exclude_keys: vec![String::from("date_added")], no_children_in_full: true, ..Default::default() };
+        let printed: Value = serde_json::from_str(&note_output(&opts, &note).unwrap()).unwrap();
+        assert_eq!(printed, json!({"id": "7", "subject": "Todo", "content": "milk"}));
+    }
+
+    #[test]
+    fn test_print_key()
+    {
+        let note = json!({"id": "7", "subject": "Shopping", "content": "milk", "position": 3});
+        let print_key = |key: &str| Options { print_key: Some(String::from(key)), ..Default::default() };
+        assert_eq!(note_output(&Options::default(), &note), Some(String::from("milk")));
+        assert_eq!(note_output(&print_key("id"), &note), Some(String::from("7")));
+        assert_eq!(note_output(&print_key("position"), &note), Some(String::from("3")));
+        assert_eq!(note_output(&print_key("url"), &note), None);
+    }
+
+    #[test]
+    fn test_output_fields()
+    {
+        let note = json!({"id": "7", "subject": "Shopping", "content": "milk\neggs", "position": 3});
+        let fields = |json: bool| Options {
+            output_fields: ["id", "position", "url", "content"].iter().map(|&field| String::from(field)).collect(),
+            json,
+            ..Default::default()
+        };
+        assert_eq!(note_output(&fields(false), &note), Some(String::from("7\t3\t\tmilk\\neggs")));
+        assert_eq!(note_output(&fields(true), &note).map(|out| serde_json::from_str::<Value>(&out).unwrap()),
+            Some(json!({"id": "7", "position": 3, "url": null, "content": "milk\neggs"})));
+
+        let args = vec!["V", "-k", "id", "-v", "7", "--print-key", "id", "--output-field", "subject"];
+        assert!(matches!(parse_args(get_string_iter(&args), None), Args::BadArgs(_)));
+    }
+
+    fn zip_archive(entries: &[(&str, &str)]) -> io::Cursor<Vec<u8>>
+    {
+        let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, contents) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            io::Write::write_all(&mut writer, contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_zip_entry()
+    {
+        let archive = zip_archive(&[("Default/Notes", "{}"), ("Default/Bookmarks", "[]")]);
+        assert_eq!(read_zip_entry(archive.clone(), None), Ok(String::from("{}")));
+        assert_eq!(read_zip_entry(archive.clone(), Some("Default/Bookmarks")), Ok(String::from("[]")));
+        assert!(read_zip_entry(archive, Some("Notes")).is_err());
+
+        let archive = zip_archive(&[("Default/Notes", "{}"), ("Profile 1/Notes", "{}")]);
+        assert!(read_zip_entry(archive, None).unwrap_err().contains("several Notes entries"));
+        let archive = zip_archive(&[("Bookmarks", "[]")]);
+        assert!(read_zip_entry(archive, None).unwrap_err().contains("no Notes entry"));
+        assert!(read_zip_entry(io::Cursor::new(b"not a zip".to_vec()), None).is_err());
+    }
+
+    #[test]
+    fn test_format_error()
+    {
+        let error = format_error(ErrorFormat::Json, Failure::Parse, "bad \"json\"", Some("Notes")).unwrap();
+        let error: Value = serde_json::from_str(&error).unwrap();
+        assert_eq!(error, json!({"error": "parse", "message": "bad \"json\"", "file": "Notes"}));
+        let error = format_error(ErrorFormat::Json, Failure::NotFound, "none", None).unwrap();
+        assert_eq!(error, r#"{"error":"not_found","message":"none"}"#);
+        assert_eq!(format_error(ErrorFormat::Text, Failure::Io, "gone", None), Some(String::from("error: gone")));
+        assert_eq!(format_error(ErrorFormat::Text, Failure::NotFound, "none", None), None);
+
+        // the format is found before the arguments are parsed, for bad arguments
+        let format = |args: &[&str]| error_format_arg(&args.iter().map(|arg| String::from(*arg)).collect::<Vec<String>>());
+        assert!(matches!(format(&["V", "--error-format", "json", "-v", "x"]), ErrorFormat::Json));
+        assert!(matches!(format(&["V", "--bogus", "--error-format=json"]), ErrorFormat::Json));
+        assert!(matches!(format(&["V", "--error-format", "json", "--error-format", "text"]), ErrorFormat::Text));
+        assert!(matches!(format(&["V", "--error-format"]), ErrorFormat::Text));
+        let error = format_error(ErrorFormat::Json, Failure::Args, "-v needs a -k", None).unwrap();
+        assert_eq!(error, r#"{"error":"args","message":"-v needs a -k"}"#);
+    }
+
+    #[test]
+    fn test_summary_head_tail()
+    {
+        let notes = json!({"children": (1..=5).map(|i| json!({"id": i.to_string()})).collect::<Vec<Value>>()});
+        let summary_ids = |head, tail| {
+            let opts = Options { head, tail, ..Default::default() };
+            let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+            summary["children"].as_array().unwrap().iter()
+                .map(|child| child.get("id").cloned().unwrap_or_else(|| child["omitted"].clone()))
+                .collect::<Vec<Value>>()
+        };
+        assert_eq!(summary_ids(None, None), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(summary_ids(Some(2), None), vec![json!("1"), json!("2"), json!(3)]);
+        assert_eq!(summary_ids(None, Some(1)), vec![json!(4), json!("5")]);
+        assert_eq!(summary_ids(Some(1), Some(1)), vec![json!("1"), json!(3), json!("5")]);
+        assert_eq!(summary_ids(Some(3), Some(3)), vec!["1", "2", "3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_summary_fields()
+    {
+        let notes = json!({"id": "1", "subject": "Root", "children": [
+            {"id": "2", "subject": "A long subject", "content": "text", "date_added": 13300000000000000_i64, "url": null},
+        ]});
+        let args = vec!["V", "summary", "--fields", "subject, date_added,url", "--truncate", "6"];
+        let Args::Key { opts, .. } = parse_args(get_string_iter(&args), None) else {
+            panic!("expected Args::Key");
+        };
+        let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+        assert_eq!(summary, json!({"subject": "Root", "children": [{"subject": "A long\u{2026}", "date_added": 13300000000000000_i64}]}));
+        let summary = summary_value(&notes, &Options::default(), &Progress::new(false)).unwrap();
+        assert_eq!(summary["children"][0], json!({"id": "2", "subject": "A long subject", "content": "text"}));
+    }
+
+    #[test]
+    fn test_summary_depth()
+    {
+        let notes = json!({"children": [
+            {"id": "1"},
+            {"id": "2", "children": [{"id": "3"}, {"id": "4", "children": [{"id": "5"}]}]},
+        ]});
+        let summary = |max_depth| summary_value(&notes, &Options { max_depth, ..Default::default() }, &Progress::new(false)).unwrap();
+        assert_eq!(summary(None), summary(Some(3)));
+        assert_eq!(summary(Some(0))["children"], json!([{"omitted": 2}]));
+        let one = summary(Some(1));
+        assert_eq!(one["children"][0], json!({"id": "1"}));
+        assert_eq!(one["children"][1]["children"], json!([{"omitted": 2}]));
+        assert_eq!(summary(Some(2))["children"][1]["children"][1]["children"], json!([{"omitted": 1}]));
+    }
+
+    #[test]
+    fn test_skip_folder()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo a"},
+            {"id": "2", "subject": "Trash", "children": [{"id": "3", "subject": "Todo b"}]},
+            {"id": "4", "subject": "Work", "children": [
+                {"id": "5", "subject": "Todo c"},
+                {"id": "6", "subject": "Old", "children": [{"id": "7", "subject": "Todo d"}]},
+            ]},
+        ]});
+        let skip = |folders: &[&str]| Options {
+            skip_folders: folders.iter().map(|&folder| String::from(folder)).collect(),
+            print_key: Some(String::from("id")),
+            ..Default::default()
+        };
+        let todo = query("subject", None, Some("Todo"));
+        assert_eq!(find_all(&todo, &skip(&[]), &notes), vec!["1", "3", "5", "7"]);
+        assert_eq!(find_all(&todo, &skip(&["Trash"]), &notes), vec!["1", "5", "7"]);
+        // ids work too, and a skipped folder hides the folders in it
+        assert_eq!(find_all(&todo, &skip(&["2", "Work"]), &notes), vec!["1"]);
+        assert_eq!(traverse_json(&todo, &skip(&["1"]), &Progress::new(false), &notes, &mut Vec::new()).map(|m| m.path), Some(vec![0]));
+        let mut remaining = 2;
+        let second = traverse_json_nth(&todo, &skip(&["Trash"]), &Progress::new(false), &notes, &mut Vec::new(), &mut remaining);
+        assert_eq!(second.map(|m| m.path), Some(vec![2, 0]));
+
+        let summary = summary_value(&notes, &skip(&["Trash", "Old"]), &Progress::new(false)).unwrap();
+        let ids: Vec<&Value> = summary["children"].as_array().unwrap().iter().map(|child| &child["id"]).collect();
+        assert_eq!(ids, vec!["1", "4"]);
+        assert_eq!(summary["children"][1]["children"].as_array().map(Vec::len), Some(1));
+        let flat: Vec<Value> = flatten(&notes, &skip(&["Work"]), &Progress::new(false)).iter().map(|note| note["id"].clone()).collect();
+        assert_eq!(flat, vec!["1", "3"]);
+    }
+
+    #[test]
+    fn test_raw_match()
+    {
+        let opts = Options { raw_match: true, ..Default::default() };
+        let note = json!({"subject": "two\nlines \"quoted\" é"});
+        assert_eq!(raw_json_string(note["subject"].as_str().unwrap()), r#"two\nlines \"quoted\" é"#);
+        assert!(value_matches(&note["subject"], &None, &Some(String::from(r"\n")), &opts));
+        assert!(value_matches(&note["subject"], &Some(String::from(r#"two\nlines \"quoted\" é"#)), &None, &opts));
+        assert!(!value_matches(&note["subject"], &None, &Some(String::from("\n")), &opts));
+        assert!(!value_matches(&note["subject"], &None, &Some(String::from(r"\n")), &Options::default()));
+    }
+
+    #[test]
+    fn test_normalize_whitespace()
+    {
+        let opts = Options { normalize_whitespace: true, ..Default::default() };
+        assert_eq!(matching_form(&opts, " Meeting\u{a0}\u{a0}notes\n\t2024 "), " Meeting notes 2024 ");
+        let note = json!({"content": "Meeting\u{a0}notes  from\tMonday"});
+        assert!(value_matches(&note["content"], &None, &Some(String::from("Meeting notes")), &opts));
+        assert!(value_matches(&note["content"], &None, &Some(String::from("notes \n from Monday")), &opts));
+        assert!(value_matches(&note["content"], &Some(String::from("Meeting notes from Monday")), &None, &opts));
+        assert!(!value_matches(&note["content"], &None, &Some(String::from("Meeting notes")), &Options::default()));
+    }
+
+    #[test]
+    fn test_regex()
+    {
+        let notes = json!({"children": [
+            {"subject": "Meeting 2023-12", "content": "old"},
+            {"subject": "2024-03 review", "content": "dated"},
+            {"subject": "2024 plans", "content": "undated"},
+        ]});
+        let opts = Options { all: true, regex: Regex::new(r"^2024-\d{2}").ok(), ..Default::default() };
+        assert_eq!(find_all(&query("subject", None, None), &opts, &notes), vec!["dated"]);
+
+        let regex_vec = vec!["V", "-k", "subject", "-r", "(unclosed", "test.json"];
+        let regex_args: Box<dyn Iterator<Item = String>> = get_string_iter(&regex_vec);
+        assert!(matches!(parse_args(regex_args, None), Args::BadArgs(_)));
+        let regex_vec = vec!["V", "-k", "subject", "-r", "a", "-c", "b", "test.json"];
+        let regex_args: Box<dyn Iterator<Item = String>> = get_string_iter(&regex_vec);
+        assert!(matches!(parse_args(regex_args, None), Args::BadArgs(_)));
+    }
+
+    #[test]
+    fn test_ignore_case()
+    {
+        let opts = Options { ignore_case: true, ..Default::default() };
+        let note = json!({"subject": "ÉTÉ Todo"});
+        assert!(value_matches(&note["subject"], &Some(String::from("été todo")), &None, &opts));
+        assert!(value_matches(&note["subject"], &None, &Some(String::from("tODO")), &opts));
+        assert!(!value_matches(&note["subject"], &None, &Some(String::from("todo")), &Options::default()));
+
+        // the pattern is compiled case-insensitively even when -i comes last
+        let regex_vec = vec!["V", "-k", "subject", "-r", "^été", "-i", "test.json"];
+        let regex_args: Box<dyn Iterator<Item = String>> = get_string_iter(&regex_vec);
+        let Args::Key { opts, .. } = parse_args(regex_args, None) else {
+            panic!("expected Args::Key");
+        };
+        assert!(value_matches(&note["subject"], &None, &None, &opts));
+
+        let notes = json!({"children": [{"content": "Rust, rust and RUST"}]});
+        let opts = Options { all: true, occurrences: true, ignore_case: true, ..Default::default() };
+        let output = run_query(Some(String::from("content")), &None, &Some(String::from("rust")), &opts, &notes, None).unwrap();
+        assert_eq!(output, Some(String::from("3\tRust, rust and RUST")));
+    }
+
+    #[test]
+    fn test_contains_word()
+    {
+        assert!(contains_word("a cat sat", "cat"));
+        assert!(contains_word("cat", "cat"));
+        assert!(contains_word("category, cat.", "cat"));
+        assert!(!contains_word("category", "cat"));
+        assert!(!contains_word("concat", "cat"));
+        assert!(!contains_word("cat_food", "cat"));
+        assert!(contains_word("café au lait", "café"));
+        assert!(!contains_word("cafés", "café"));
+    }
+
+    #[test]
+    fn test_read_error()
+    {
+        let missing = Input::File(String::from("does_not_exist.json"));
+        assert_eq!(missing.read(), Err(String::from("could not read 'does_not_exist.json': file not found")));
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert_eq!(read_error("Notes", &denied), "could not read 'Notes': permission denied");
+    }
+
+    #[test]
+    fn test_child_count()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [{"subject": "a", "content": ""}, {"subject": "b", "content": ""}]},
+            {"subject": "Empty", "children": []},
+        ]});
+        let opts = Options { child_count: true, ..Default::default() };
+        let count = |subject: &str| find_first(&query("subject", Some(subject), None), &opts, &notes);
+        assert_eq!(count("Work"), Some(String::from("2")));
+        assert_eq!(count("Empty"), Some(String::from("0")));
+        assert_eq!(count("a"), Some(String::from("0")));
+        assert_eq!(count("Home"), None);
+    }
+
+    #[test]
+    fn test_children_only()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [
+                {"subject": "Projects", "children": [{"subject": "deep"}]},
+                {"id": "7", "content": ""},
+                {"content": "no subject or id"},
+            ]},
+            {"subject": "Empty", "children": []},
+        ]});
+        let opts = Options { children_only: true, ..Default::default() };
+        let children = |subject: &str| find_first(&query("subject", Some(subject), None), &opts, &notes);
+        assert_eq!(children("Work"), Some(String::from("Projects\n7\nno subject or id")));
+        assert_eq!(children("Empty"), Some(String::new()));
+        assert_eq!(children("Home"), None);
+    }
+
+    #[test]
+    fn test_replace_in_notes()
+    {
+        let mut notes = json!({"children": [
+            {"subject": "Todo", "content": "[ ] milk\n[ ] eggs"},
+            {"subject": "Done", "content": "[ ] untouched"},
+            {"children": [{"subject": "Todo", "content": "[ ] bread"}, {"subject": "Todo"}]},
+        ]});
+        let todo = query("subject", Some("Todo"), None);
+        assert_eq!(replace_in_notes(&todo, &Options::default(), &mut notes, "[ ]", "[x]"), 3);
+        assert_eq!(notes, json!({"children": [
+            {"subject": "Todo", "content": "[x] milk\n[x] eggs"},
+            {"subject": "Done", "content": "[ ] untouched"},
+            {"children": [{"subject": "Todo", "content": "[x] bread"}, {"subject": "Todo"}]},
+        ]}));
+        assert_eq!(replace_in_notes(&todo, &Options::default(), &mut notes, "[ ]", "[x]"), 0);
+
+        // notes in a skipped folder are not edited
+        let mut notes = json!({"children": [
+            {"subject": "Todo", "content": "[ ] milk", "children": []},
+            {"subject": "Trash", "children": [{"subject": "Todo", "content": "[ ] old", "children": []}]},
+        ]});
+        let opts = Options { skip_folders: vec![String::from("Trash")], ..Default::default() };
+        assert_eq!(replace_in_notes(&todo, &opts, &mut notes, "[ ]", "[x]"), 1);
+        assert_eq!(notes["children"][1]["children"][0]["content"], "[ ] old");
+    }
+
+    #[test]
+    fn test_merge_notes()
+    {
+        let primary = json!({"children": [
+            {"id": "1", "subject": "Work", "children": [
+                {"id": "2", "content": "old", "date_added": "10"},
+                {"id": "3", "content": "kept", "date_added": "10", "date_modified": "30"},
+            ]},
+            {"id": "4", "content": "root note", "date_added": "10"},
+        ]});
+        let secondary = json!({"children": [
+            {"id": "1", "subject": "Work renamed", "children": [
+                {"id": "2", "content": "new", "date_added": "10", "date_modified": "20"},
+                {"id": "3", "content": "stale", "date_added": "20"},
+                {"id": "5", "content": "added to work", "date_added": "20"},
+            ]},
+            {"id": "6", "subject": "Home", "children": [
+                {"id": "7", "content": "added to home", "date_added": "20"},
+                {"id": "4", "content": "moved root note", "date_added": "20"},
+            ]},
+        ]});
+        let merged = merge_notes(&primary, &secondary);
+        assert_eq!(merged, json!({"children": [
+            {"id": "1", "subject": "Work", "children": [
+                {"id": "2", "content": "new", "date_added": "10", "date_modified": "20"},
+                {"id": "3", "content": "kept", "date_added": "10", "date_modified": "30"},
+                {"id": "5", "content": "added to work", "date_added": "20"},
+            ]},
+            {"id": "4", "content": "moved root note", "date_added": "20"},
+            {"id": "6", "subject": "Home", "children": [
+                {"id": "7", "content": "added to home", "date_added": "20"},
+            ]},
+        ]}));
+    }
+
+    #[test]
+    fn test_compact_empty()
+    {
+        let notes = json!({"children": [{"id": "1", "subject": "", "content": "text"}, {"id": "2", "content": ""}]});
+        let opts = Options { compact_empty: true, ..Default::default() };
+        let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+        assert_eq!(summary, json!({"children": [{"id": "1", "content": "text"}, {"id": "2"}]}));
+        let summary = summary_value(&notes, &Options::default(), &Progress::new(false)).unwrap();
+        assert_eq!(summary["children"][1], json!({"id": "2", "content": ""}));
+    }
+
+    #[test]
+    fn test_indent_preview()
+    {
+        let notes = json!({"content": "root", "children": [
+            {"id": "1", "content": "top"},
+            {"subject": "Work", "children": [{"id": "2", "content": "nested"}, {"id": "3", "content": ""}]},
+        ]});
+        let opts = Options { indent_preview: true, ..Default::default() };
+        let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+        assert_eq!(summary, json!({"content": "root", "children": [
+            {"id": "1", "content": "  top"},
+            {"subject": "Work", "children": [{"id": "2", "content": "    nested"}, {"id": "3", "content": ""}]},
+        ]}));
+    }
+
+    #[test]
+    fn test_summary_truncation()
+    {
+        let long = "x".repeat(40);
+        let notes = json!({"children": [{"subject": long, "content": long}]});
+        let summary = summary_value(&notes, &Options::default(), &Progress::new(false)).unwrap();
+        assert_eq!(summary["children"][0], json!({"subject": format!("{}\u{2026}", "x".repeat(30)), "content": format!("{}\u{2026}", "x".repeat(30))}));
+        let opts = Options { truncate_subject: Some(0), truncate_content: Some(5), ..Default::default() };
+        let summary = summary_value(&notes, &opts, &Progress::new(false)).unwrap();
+        assert_eq!(summary["children"][0], json!({"subject": long, "content": "xxxxx\u{2026}"}));
+    }
+
+    #[test]
+    fn test_serialize_yaml()
+    {
+        let opts = Options { format: Some(OutputFormat::Yaml), ..Default::default() };
+        let notes = json!({"id": "1", "content": "first line\nsecond line"});
+        assert_eq!(serialize(&opts, &notes).unwrap(), "id: '1'\ncontent: |-\n  first line\n  second line");
+        assert_eq!(serialize(&Options::default(), &json!({"id": "1"})).unwrap(), "{\n  \"id\": \"1\"\n}");
+    }
+
+    #[test]
+    fn test_split_file_list()
+    {
+        assert_eq!(split_file_list(b"a/Notes\0b c/Notes.bak\0"), vec!["a/Notes", "b c/Notes.bak"]);
+        assert_eq!(split_file_list(b"Notes"), vec!["Notes"]);
+        assert!(split_file_list(b"").is_empty());
+    }
+
+    #[test]
+    fn test_truncate_bytes()
+    {
+        assert_eq!(truncate_bytes(String::from("short"), 5), "short");
+        assert_eq!(truncate_bytes(String::from("longer"), 4), "long\u{2026}[truncated]");
+        // the e with an acute accent takes two bytes and is not cut in half
+        assert_eq!(truncate_bytes(String::from("caf\u{e9}s"), 4), "caf\u{2026}[truncated]");
+        assert_eq!(truncate_bytes(String::from("abc"), 0), "\u{2026}[truncated]");
+
+        let opts = Options { max_content_bytes: Some(3), ..Default::default() };
+        assert_eq!(note_output(&opts, &json!({"content": "abcdef"})), Some(String::from("abc\u{2026}[truncated]")));
+    }
+
+    #[test]
+    fn test_index_path()
+    {
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let opts = Options { index_path: true, ..Default::default() };
+        assert_eq!(find_first(&query("id", Some("3"), None), &opts, &notes), Some(String::from("1/0")));
+        assert_eq!(find_all(&query("content", None, Some("t")), &opts, &notes), vec!["1/0", "2"]);
+
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test_array.json").unwrap()).unwrap();
+        assert_eq!(find_first(&query("id", Some("2"), None), &opts, &notes), Some(String::from("1/0")));
+    }
+
+    #[test]
+    fn test_depth_of()
+    {
+        let opts = Options { depth_of: true, ..Default::default() };
+        let depth = |notes: &Value, id: &str| run_query(Some(String::from("id")), &Some(String::from(id)), &None, &opts, notes, None).unwrap();
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        assert_eq!(depth(&notes, "1"), Some(String::from("1")));
+        assert_eq!(depth(&notes, "3"), Some(String::from("2")));
+        assert_eq!(depth(&notes, "9"), None);
+        // the notes directly under each root of an array file are at depth 1 too
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test_array.json").unwrap()).unwrap();
+        assert_eq!(depth(&notes, "2"), Some(String::from("1")));
+    }
+
+    #[test]
+    fn test_id_contains()
+    {
+        let notes = json!({"children": [
+            {"id": "a1b2c3d4", "content": "first"},
+            {"id": "e5f6a7b8", "content": "second"},
+            {"id": "c9d0e1f2", "subject": "Third", "content": "third"},
+        ]});
+        let opts = Options::default();
+        assert_eq!(find_first(&query("id", None, Some("f6a7")), &opts, &notes), Some(String::from("second")));
+        assert_eq!(find_first(&query("id", None, Some("c")), &opts, &notes), Some(String::from("first")));
+        assert_eq!(find_all(&query("id", None, Some("e1")), &opts, &notes), vec!["third"]);
+        assert_eq!(find_first(&query("id", None, Some("zz")), &opts, &notes), None);
+    }
+
+    #[test]
+    fn test_match_without_content()
+    {
+        let notes = json!({"children": [
+            {"subject": "Empty folder", "children": []},
+            {"subject": "Numbered", "content": 5},
+            {"subject": "Todo", "content": "text"},
+        ]});
+        let opts = Options::default();
+        assert_eq!(find_first(&query("subject", Some("Empty folder"), None), &opts, &notes), Some(String::new()));
+        assert_eq!(find_first(&query("subject", None, Some("Numb")), &opts, &notes), Some(String::new()));
+        assert_eq!(find_all(&query("subject", None, Some("m")), &opts, &notes), vec!["", ""]);
+        let opts = Options { print_key: Some(String::from("subject")), ..Default::default() };
+        assert_eq!(find_first(&query("subject", None, Some("folder")), &opts, &notes), Some(String::from("Empty folder")));
+    }
+
+    #[test]
+    fn test_sanitize_file_name()
+    {
+        assert_eq!(sanitize_file_name("Todo queue"), "Todo queue");
+        assert_eq!(sanitize_file_name(" Work/Projects\\2024 \t\n plans "), "WorkProjects2024 plans");
+        assert_eq!(sanitize_file_name("../"), "..");
+        assert_eq!(sanitize_file_name("/"), "");
+    }
+
+    #[test]
+    fn test_write_matches()
+    {
+        let dir = env::temp_dir().join(format!("vivaldi_notes_parser_test_{}", std::process::id()));
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo", "content": "first"},
+            {"id": "2", "subject": "Todo", "content": "second"},
+            {"id": "3", "subject": "/", "content": "third"},
+        ]});
+        let mut matches = Vec::new();
+        let query = query("content", None, Some(""));
+        traverse_json_all(&query, &Options::default(), &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+        let written = write_matches(&dir, &Options::default(), &matches).unwrap();
+        assert_eq!(written, vec![dir.join("Todo.txt"), dir.join("Todo (2).txt"), dir.join("3.txt")]);
+        assert_eq!(fs::read_to_string(dir.join("Todo (2).txt")).unwrap(), "second");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_attachments()
+    {
+        let dir = env::temp_dir().join(format!("vivaldi_notes_parser_attachments_{}", std::process::id()));
+        let notes = json!({"children": [
+            {"id": "1", "content": "see picture", "attachments": [
+                {"id": "a1", "filename": "photo.jpg", "data": "aGVsbG8="},
+                {"id": "a2"},
+            ]},
+            {"id": "2", "type": "attachment", "subject": "Logo", "content": "data:image/svg+xml;base64,PHN2Zy8+"},
+            {"id": 3, "type": "attachment", "data": "aGVs\nbG8="},
+        ]});
+        let written = extract_attachments(&dir, &Options::default(), &notes, &Progress::new(false)).unwrap();
+        assert_eq!(written, vec![dir.join("photo.jpg"), dir.join("Logo.svg"), dir.join("3")]);
+        assert_eq!(fs::read(dir.join("photo.jpg")).unwrap(), b"hello");
+        assert_eq!(fs::read(dir.join("Logo.svg")).unwrap(), b"<svg/>");
+        assert_eq!(fs::read(dir.join("3")).unwrap(), b"hello");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fill_template()
+    {
+        let note = json!({"id": "7", "subject": "Shopping", "content": "milk", "position": 3});
+        assert_eq!(fill_template("{id}: {subject}\n{content}\n", &note), "7: Shopping\nmilk\n");
+        assert_eq!(fill_template("[{url}] #{position}", &note), "[] #3");
+        assert_eq!(fill_template("no placeholders", &note), "no placeholders");
+        assert_eq!(fill_template("{id} {unterminated", &note), "7 {unterminated");
+    }
+
+    #[test]
+    fn test_number_lines()
+    {
+        assert_eq!(number_lines("one\ntwo"), "     1\tone\n     2\ttwo");
+        assert_eq!(number_lines(""), "     1\t");
+    }
+
+    #[test]
+    fn test_strip_bom()
+    {
+        let with_bom = "\u{FEFF}{\"children\": []}";
+        assert!(serde_json::from_str::<Value>(with_bom).is_err());
+        let (stripped, had_bom) = strip_bom(with_bom);
+        assert!(had_bom);
+        assert_eq!(serde_json::from_str::<Value>(stripped).unwrap(), json!({"children": []}));
+        assert_eq!(strip_bom("{}"), ("{}", false));
+    }
+
+    #[test]
+    fn test_show_path()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [{"subject": "", "children": [{"id": "1", "subject": "Todo Queue", "content": "a"}]}]},
+            {"id": "2", "content": "b"},
+        ]});
+        assert_eq!(breadcrumbs(&notes, &[0, 0, 0]), "Work/<untitled>/Todo Queue");
+        assert_eq!(breadcrumbs(&notes, &[1]), "2");
+        assert_eq!(breadcrumbs(&json!([notes]), &[0, 0, 0, 0]), "Work/<untitled>/Todo Queue");
+
+        let opts = Options { show_path: true, ..Default::default() };
+        let m = traverse_json(&query("id", Some("1"), None), &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        assert_eq!(render_match(&opts, &notes, &m).as_deref(), Some("Work/<untitled>/Todo Queue\ta"));
+    }
+
+    #[test]
+    fn test_resolve_path()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [{"subject": "Projects", "children": [{"subject": "Other", "content": "no"}]}]},
+            {"subject": "Work", "children": [
+                {"subject": "Projects", "children": [{"subject": "Todo Queue", "content": "first"}]},
+                {"subject": "Projects", "children": [{"subject": "Todo Queue", "content": "second"}]},
+            ]},
+        ]});
+        let resolve = |opts: &Options, notes: &Value| run_query(None, &None, &None, opts, notes, None).unwrap();
+        let opts = Options { note_path: Some(String::from("Work/Projects/Todo Queue")), ..Default::default() };
+        assert_eq!(resolve(&opts, &notes).as_deref(), Some("first"));
+        assert_eq!(resolve(&opts, &json!([{"children": []}, notes])).as_deref(), Some("first"));
+        let opts = Options { all: true, note_path: Some(String::from("/Work/Projects/Todo Queue")), ..Default::default() };
+        assert_eq!(resolve(&opts, &notes).as_deref(), Some("first\nsecond"));
+        let opts = Options { note_path: Some(String::from("Work/Todo Queue")), ..Default::default() };
+        assert_eq!(resolve(&opts, &notes), None);
+        let opts = Options {
+            all: true,
+            note_path: Some(String::from("Work/Projects/Todo Queue")),
+            skip_folders: vec![String::from("Projects")],
+            ..Default::default()
+        };
+        assert_eq!(resolve(&opts, &notes), None);
+    }
+
+    #[test]
+    fn test_dump_path()
+    {
+        let notes = json!({"children": [{"id": "1"}, {"subject": "Folder", "children": [{"id": "2", "subject": "a/b~c"}]}]});
+        let opts = Options { dump_path: true, ..Default::default() };
+        let m = traverse_json(&query("id", Some("2"), None), &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        let output = render_match(&opts, &notes, &m).unwrap();
+        assert_eq!(output, "/children/1/children/0\ta/b~c");
+        assert_eq!(notes.pointer("/children/1/children/0"), Some(m.note));
+        let roots = json!([{"id": "1"}, {"children": [{"id": "2"}]}]);
+        assert_eq!(json_pointer(&roots, &[1, 0]), "/1/children/0");
+        assert_eq!(json_pointer(&roots, &[]), "");
+    }
+
+    #[test]
+    fn test_byte_offsets()
+    {
+        let raw = fs::read_to_string("test.json").unwrap();
+        let notes: Value = serde_json::from_str(&raw).unwrap();
+        let mut matches = Vec::new();
+        traverse_json_all(&query("content", None, Some("t")), &Options::default(), &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+        let offsets = byte_offsets(&raw, "content", &matches);
+        assert_eq!(offsets.len(), 2);
+        // stdin is read as it is, so the offsets are those of the file
+        let piped = read_all(fs::File::open("test.json").unwrap(), "stdin").unwrap();
+        assert_eq!(byte_offsets(&piped, "content", &matches), offsets);
+        for (offset, m) in offsets.iter().zip(&matches) {
+            let offset: usize = offset.parse().unwrap();
+            let escaped = serde_json::to_string(&m.note["content"]).unwrap();
+            assert_eq!(&raw[offset - 1..offset - 1 + escaped.len()], escaped);
+        }
+        let repeated = r#"{"children": [{"content": "same"}, {"content": "same"}]}"#;
+        let notes: Value = serde_json::from_str(repeated).unwrap();
+        let mut matches = Vec::new();
+        traverse_json_all(&query("content", Some("same"), None), &Options::default(), &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+        assert_eq!(byte_offsets(repeated, "content", &matches), vec!["27", "48"]);
+        assert_eq!(byte_offsets(r#"{"content": "\u0061"}"#, "content", &[Match { note: &json!({"content": "a"}), path: vec![] }]), vec!["unknown"]);
+    }
+
+    #[test]
+    fn test_context()
+    {
+        let notes = json!({"children": (1..=6).map(|i| json!({"id": i.to_string(), "subject": format!("Note {i}")})).collect::<Vec<Value>>()});
+        let opts = Options { context: Some(1), print_key: Some(String::from("id")), ..Default::default() };
+        let m = traverse_json(&query("id", Some("4"), None), &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        assert_eq!(render_match(&opts, &notes, &m).unwrap(), "4\n--- context\n  Note 3\n> Note 4\n  Note 5");
+        let opts = Options { context: Some(2), ..opts };
+        let m = traverse_json(&query("id", Some("1"), None), &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        assert_eq!(render_match(&opts, &notes, &m).unwrap(), "1\n--- context\n> Note 1\n  Note 2\n  Note 3");
+    }
+
+    #[test]
+    fn test_visit_notes()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "children": []},
+            {"id": "2", "children": [{"id": "3"}, {"id": "4"}]},
+            {"id": "5"},
+        ]});
+        let visited = |opts: &Options, stop: &str| {
+            let mut ids = Vec::new();
+            let _ = visit_notes(opts, &Progress::new(false), &notes, &mut Vec::new(), &mut |note, path| {
+                ids.push(format!("{}@{:?}", note["id"].as_str().unwrap_or("root"), path));
+                if note["id"] == stop { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+            });
+            ids
+        };
+        assert_eq!(visited(&Options::default(), ""), vec!["1@[0]", "3@[1, 0]", "4@[1, 1]", "5@[2]"]);
+        assert_eq!(visited(&Options::default(), "3"), vec!["1@[0]", "3@[1, 0]"]);
+        let opts = Options { child_count: true, post_order: true, ..Default::default() };
+        assert_eq!(visited(&opts, ""), vec!["1@[0]", "3@[1, 0]", "4@[1, 1]", "2@[1]", "5@[2]", "root@[]"]);
+        let opts = Options { skip_folders: vec![String::from("2")], ..Default::default() };
+        assert_eq!(visited(&opts, ""), vec!["1@[0]", "5@[2]"]);
+    }
+
+    #[test]
+    fn test_traverse_json_last()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo"},
+            {"children": [{"id": "2", "subject": "Todo"}, {"id": "3", "subject": "Done"}]},
+            {"id": "4", "subject": "Done"},
+        ]});
+        let opts = Options { print_key: Some(String::from("id")), ..Default::default() };
+        let last = |val| traverse_json_last(&query("subject", Some(val), None), &opts, &Progress::new(false), &notes, &mut Vec::new());
+        let m = last("Todo").unwrap();
+        assert_eq!((match_output(&opts, &m), m.path), (Some(String::from("2")), vec![1, 0]));
+        assert_eq!(last("Done").and_then(|m| match_output(&opts, &m)), Some(String::from("4")));
+        assert!(last("Missing").is_none());
+    }
+
+    #[test]
+    fn test_traverse_json_nth()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Todo"},
+            {"children": [{"id": "2", "subject": "Todo"}, {"id": "3", "subject": "Done"}]},
+            {"id": "4", "subject": "Todo"},
+        ]});
+        let opts = Options { print_key: Some(String::from("id")), ..Default::default() };
+        let nth = |n: usize| {
+            let m = traverse_json_nth(&query("subject", Some("Todo"), None), &opts, &Progress::new(false), &notes, &mut Vec::new(), &mut n.clone());
+            m.map(|m| (match_output(&opts, &m), m.path))
+        };
+        assert_eq!(nth(1), Some((Some(String::from("1")), vec![0])));
+        assert_eq!(nth(2), Some((Some(String::from("2")), vec![1, 0])));
+        assert_eq!(nth(3), Some((Some(String::from("4")), vec![2])));
+        assert_eq!(nth(4), None);
+    }
+
+    #[test]
+    fn test_post_order()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Work", "children": [
+                {"id": "2", "subject": "Work notes"},
+                {"id": "3", "subject": "Work folder", "children": [{"id": "4", "subject": "Work item"}]},
+            ]},
+        ]});
+        let work = query("subject", None, Some("Work"));
+        let opts = Options { child_count: true, ..Default::default() };
+        let ids = |opts: &Options| {
+            let mut matches = Vec::new();
+            traverse_json_all(&work, opts, &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+            matches.iter().map(|m| m.note["id"].as_str().unwrap()).collect::<Vec<&str>>()
+        };
+        assert_eq!(ids(&opts), vec!["1", "2", "3", "4"]);
+        let opts = Options { post_order: true, ..opts };
+        assert_eq!(ids(&opts), vec!["2", "4", "3", "1"]);
+        let first = traverse_json(&work, &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        let last = traverse_json_last(&work, &opts, &Progress::new(false), &notes, &mut Vec::new()).unwrap();
+        assert_eq!((first.note["id"].as_str(), last.note["id"].as_str()), (Some("2"), Some("1")));
+    }
+
+    #[test]
+    fn test_json_array()
+    {
+        let notes = json!({"children": [{"id": "1", "content": "Todo a"}, {"id": "2", "content": "done"}, {"id": "3", "content": "Todo b"}]});
+        let mut matches = Vec::new();
+        traverse_json_all(&query("content", None, Some("Todo")), &Options::default(), &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+        let array = json_array(&Options::default(), &matches);
+        assert_eq!(Value::Array(array), json!([{"id": "1", "content": "Todo a"}, {"id": "3", "content": "Todo b"}]));
+        assert!(json_array(&Options::default(), &[]).is_empty());
+        let opts = Options { exclude_keys: vec![String::from("content"), String::from("missing")], ..Default::default() };
+        assert_eq!(Value::Array(json_array(&opts, &matches)), json!([{"id": "1"}, {"id": "3"}]));
+
+        let folder = json!({"id": "f", "subject": "Folder", "children": [{"id": "1"}]});
+        let opts = Options { no_children_in_full: true, exclude_keys: vec![String::from("subject")], ..Default::default() };
+        assert_eq!(without_excluded(&opts, &folder), json!({"id": "f"}));
+    }
+
+    #[test]
+    fn test_found_extend()
+    {
+        let opts = Options::default();
+        let mut found = Found::Array(vec![json!({"id": "1"})]);
+        found.extend(Found::Array(vec![json!({"id": "2"})]));
+        let array: Value = serde_json::from_str(&found.render(&opts).unwrap()).unwrap();
+        assert_eq!(array, json!([{"id": "1"}, {"id": "2"}]));
+
+        let a = json!({"id": "1", "content": "a"});
+        let b = json!({"id": "2", "content": "b"});
+        let mut found = Found::Outlines(opml_outlines(&[&a]));
+        found.extend(Found::Outlines(opml_outlines(&[&b])));
+        assert_eq!(found.render(&opts).unwrap(), opml(&opml_outlines(&[&a, &b])));
+        assert_eq!(opml(&opml_outlines(&[&a, &b])).matches("<opml").count(), 1);
+    }
+
+    #[test]
+    fn test_sample_matches()
+    {
+        let notes = json!({"children": (1..=20).map(|i| json!({"id": i.to_string(), "content": "Todo"})).collect::<Vec<Value>>()});
+        let opts = Options { print_key: Some(String::from("id")), ..Default::default() };
+        let sample = |n, seed| {
+            let mut matches = Vec::new();
+            traverse_json_all(&query("content", Some("Todo"), None), &opts, &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+            sample_matches(matches, n, seed).iter().filter_map(|m| match_output(&opts, m)).collect::<Vec<String>>()
+        };
+        let picked = sample(5, Some(42));
+        assert_eq!(picked.len(), 5);
+        assert_eq!(picked, sample(5, Some(42)));
+        // the sample keeps the tree order
+        let mut sorted = picked.clone();
+        sorted.sort_by_key(|id| id.parse::<usize>().unwrap());
+        assert_eq!(picked, sorted);
+        assert_eq!(sample(30, None).len(), 20);
+    }
+
+    #[test]
+    fn test_note_label()
+    {
+        assert_eq!(note_label(&json!({"subject": "Todo", "id": "1"})), "Todo");
+        assert_eq!(note_label(&json!({"subject": "", "id": "1"})), "1");
+        assert_eq!(note_label(&json!({"content": "some content"})), "some content");
+        assert_eq!(note_label(&json!({})), "(untitled)");
+    }
+
+    #[test]
+    fn test_folder_counts()
+    {
+        let notes = json!({"children": [
+            {"subject": "Small", "children": [{"content": "a", "children": []}]},
+            {"content": "loose note", "children": []},
+            {"subject": "Big", "children": [
+                {"content": "b", "children": []},
+                {"subject": "Nested", "children": [{"content": "c", "children": []}, {"content": "d"}]},
+            ]},
+        ]});
+        let counts = folder_counts(&notes, &Progress::new(false));
+        let expected = vec![(String::from("Big"), 3), (String::from("Small"), 1)];
+        assert_eq!(counts, expected);
+
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let counts = folder_counts(&notes, &Progress::new(false));
+        assert_eq!(counts, vec![(String::from("(untitled)"), 1)]);
+
+        // the roots of an array file are not folders of their own
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test_array.json").unwrap()).unwrap();
+        assert_eq!(folder_counts(&notes, &Progress::new(false)), vec![]);
+        let notes = json!([
+            {"children": [{"subject": "Work", "children": [{"content": "a"}, {"content": "b"}]}]},
+            {"children": [{"subject": "Home", "children": [{"content": "c"}]}, {"content": "loose note"}]},
+        ]);
+        let expected = vec![(String::from("Work"), 2), (String::from("Home"), 1)];
+        assert_eq!(folder_counts(&notes, &Progress::new(false)), expected);
+    }
+
+    #[test]
+    fn test_parse_strict()
+    {
+        let duplicate = fs::read_to_string("test_duplicate_keys.json").unwrap();
+        let lenient: Value = serde_json::from_str(&duplicate).unwrap();
+        assert_eq!(lenient["children"][0]["content"], "silently replaces the first content");
+        let error = parse_strict(&duplicate).unwrap_err().to_string();
+        assert!(error.starts_with("duplicate key 'content' at line 7"), "{}", error);
+
+        let trailing = fs::read_to_string("test_trailing_data.json").unwrap();
+        let error = parse_strict(&trailing).unwrap_err().to_string();
+        assert!(error.starts_with("trailing characters at line 11"), "{}", error);
+
+        let notes = fs::read_to_string("test.json").unwrap();
+        assert_eq!(parse_strict(&notes).unwrap(), serde_json::from_str::<Value>(&notes).unwrap());
+    }
+
+    #[test]
+    fn test_find_folder()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Work", "children": []},
+            {"id": "2", "subject": "Archive", "children": [
+                {"id": "3", "subject": "Work", "children": [{"id": "4", "subject": "Old"}]},
+            ]},
+            {"id": "5", "subject": "Work", "children": [{"id": "6", "subject": "New"}]},
+        ]});
+        // the note with an empty children array is not a folder
+        let mut path = Vec::new();
+        assert!(find_folder(&Options::default(), &notes, "Work", &mut path));
+        assert_eq!(json_pointer(&notes, &path), "/children/1/children/0");
+        let mut path = Vec::new();
+        assert!(find_folder(&Options::default(), &notes, "5", &mut path));
+        assert_eq!(path, vec![2]);
+        assert!(!find_folder(&Options::default(), &notes, "Old", &mut Vec::new()));
+
+        let roots = json!([notes]);
+        let mut path = Vec::new();
+        assert!(find_folder(&Options::default(), &roots, "Archive", &mut path));
+        assert_eq!(json_pointer(&roots, &path), "/0/children/1");
+
+        // skipped folders are not looked into
+        let opts = Options { skip_folders: vec![String::from("Archive")], ..Default::default() };
+        let mut path = Vec::new();
+        assert!(find_folder(&opts, &notes, "Work", &mut path));
+        assert_eq!(path, vec![2]);
+    }
+
+    #[test]
+    fn test_notes_root()
+    {
+        let notes = json!({"notes": {"children": [{"id": "1", "content": "wrapped"}]}});
+        let opts = Options { root_path: Some(String::from("/notes")), print_key: Some(String::from("id")), ..Default::default() };
+        let root = notes_root(&opts, &notes).unwrap();
+        assert_eq!(find_first(&query("content", Some("wrapped"), None), &opts, root), Some(String::from("1")));
+        assert!(notes_root(&Options { root_path: Some(String::from("/missing")), ..Default::default() }, &notes).is_err());
+        assert_eq!(notes_root(&Options::default(), &notes).unwrap(), &notes);
+    }
+
+    #[test]
+    fn test_flatten()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "content": "loose", "children": []},
+            {"subject": "Work", "children": [
+                {"subject": "", "children": [{"id": "2", "content": "deep", "children": []}]},
+                {"id": "3", "content": "bare"},
+            ]},
+        ]});
+        let opts = Options { with_path: true, ..Default::default() };
+        let flat = flatten(&notes, &opts, &Progress::new(false));
+        assert_eq!(flat, vec![
+            json!({"id": "1", "content": "loose", "children": [], "path": ""}),
+            json!({"id": "2", "content": "deep", "children": [], "path": "Work/<untitled>"}),
+            json!({"id": "3", "content": "bare", "path": "Work"}),
+        ]);
+        let roots = json!([notes, {"children": [{"id": "4", "children": []}]}]);
+        assert_eq!(flatten(&roots, &Options::default(), &Progress::new(false)).len(), 4);
+
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let ids: Vec<_> = flatten(&notes, &opts, &Progress::new(false)).iter()
+            .map(|note| (note["id"].as_str().unwrap_or_default().to_owned(), note["path"].as_str().unwrap().to_owned()))
+            .collect();
+        assert_eq!(ids, vec![
+            (String::from("1"), String::new()),
+            (String::from("3"), String::from("<untitled>")),
+            (String::new(), String::new()),
+        ]);
+    }
+
+    #[test]
+    fn test_group_by_folder()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work", "children": [{"content": "TODO a"}, {"content": "done"}, {"content": "TODO b"}]},
+            {"content": "TODO loose"},
+            {"subject": "Personal", "children": [{"content": "TODO c"}]},
+        ]});
+        let opts = Options { all: true, ..Default::default() };
+        let todo = query("content", None, Some("TODO"));
+        let mut matches = Vec::new();
+        traverse_json_all(&todo, &opts, &Progress::new(false), &notes, &mut Vec::new(), &mut matches);
+        assert_eq!(
+            group_by_folder(&opts, &notes, &matches).as_deref(),
+            Some("Work: [2 matches]\nTODO a\nTODO b\n\n(root): [1 match]\nTODO loose\n\nPersonal: [1 match]\nTODO c")
+        );
+        assert_eq!(group_by_folder(&opts, &notes, &[]), None);
+    }
+
+    #[test]
+    fn test_schema()
+    {
+        let old = json!({"children": [{"subject": "Work", "children": [{"id": "1", "dateCreated": "10"}]}]});
+        assert_eq!(Schema::detect(&old), Schema::Vivaldi5);
+        assert_eq!(Schema::detect(&json!([{"children": [{"id": "1", "date_added": "10"}]}])), Schema::Vivaldi6);
+        assert_eq!(Schema::detect(&json!({"children": [{"id": "1"}]})), Schema::Vivaldi6);
+
+        let mut notes = old.clone();
+        assert_eq!(adapt_schema(&Options::default(), &mut notes), Schema::Vivaldi5);
+        assert_eq!(notes, json!({"children": [{"subject": "Work", "children": [{"id": "1", "date_added": "10"}]}]}));
+        // a given schema is used as it is
+        let mut notes = old.clone();
+        let opts = Options { schema: Some(Schema::Vivaldi6), ..Default::default() };
+        assert_eq!(adapt_schema(&opts, &mut notes), Schema::Vivaldi6);
+        assert_eq!(notes, old);
+    }
+
+    #[test]
+    fn test_note_hash()
+    {
+        // printf 'a\0b' | sha256sum
+        let hash = note_hash(&json!({"subject": "a", "content": "b", "id": "1"}));
+        assert_eq!(hash, "59b271ae1bbcb1d31d41929817f4b16fb439eb4f31520b5ad1d5ce98920a7138");
+        assert_ne!(hash, note_hash(&json!({"subject": "ab", "content": ""})));
+        assert_eq!(note_hash(&json!({"content": "b"})), note_hash(&json!({"subject": "", "content": "b"})));
+    }
+
+    #[test]
+    fn test_top_longest()
+    {
+        let notes = json!({"children": [
+            {"id": "2", "subject": "Tie b", "content": "abc", "children": []},
+            {"subject": "Work", "children": [{"id": "1", "subject": "Tie a", "content": "xyz", "children": []}, {"id": "4", "content": "é"}]},
+            {"id": "3", "subject": "Longest", "content": "abcdef", "children": []},
+        ]});
+        let longest = top_longest(&notes, &Progress::new(false), 3);
+        let expected = vec![(String::from("Longest"), 6), (String::from("Tie a"), 3), (String::from("Tie b"), 3)];
+        assert_eq!(longest, expected);
+        assert_eq!(top_longest(&notes, &Progress::new(false), 10).len(), 4);
+        assert_eq!(top_longest(&notes, &Progress::new(false), 0), vec![]);
+
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let longest = top_longest(&notes, &Progress::new(false), 3);
+        assert_eq!(longest, vec![(String::from("Todo queue"), 11), (String::from("1"), 11), (String::from("3"), 4)]);
+    }
+
+    #[test]
+    fn test_notes_tree()
+    {
+        let tree = NotesTree::from_reader(fs::File::open("test.json").unwrap()).unwrap();
+        assert_eq!(tree.find("id", "3").and_then(|note| note.content.as_deref()), Some("test"));
+        assert_eq!(tree.find("id", "9"), None);
+        assert_eq!(tree.summary()["children"][0], json!({"id": "1", "content": "hello\nworld"}));
+        assert!(NotesTree::from_reader("{".as_bytes()).is_err());
+
+        // the tool prints the same summary
+        for file in ["test.json", "test_array.json"] {
+            let tree = NotesTree::from_reader(fs::File::open(file).unwrap()).unwrap();
+            let notes: Value = serde_json::from_str(&fs::read_to_string(file).unwrap()).unwrap();
+            assert_eq!(tree.summary(), summary_value(&notes, &Options::default(), &Progress::new(false)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_load_malformed()
+    {
+        let file = env::temp_dir().join(format!("vivaldi_notes_parser_malformed_{}.json", std::process::id()));
+        fs::write(&file, r#"{"children": [{"id": "1", "subject": 5, "children": []}]}"#).unwrap();
+        let input = Input::File(file.to_string_lossy().into_owned());
+        let (failure, message) = input.load(&Options::default(), &mut Timing::default()).unwrap_err();
+        assert_eq!(failure, Failure::Parse);
+        assert!(message.contains("invalid type: integer `5`, expected a string"), "{}", message);
+        fs::remove_file(&file).unwrap();
+
+        assert!(Input::File(String::from("test.json")).load(&Options::default(), &mut Timing::default()).is_ok());
+    }
+
+    #[test]
+    fn test_notes_tree_iter()
+    {
+        let text = r#"[
+            {"children": [{"id": "1"}, {"subject": "Work", "children": [{"subject": "", "children": [{"id": "2"}]}, {"id": "3"}]}]},
+            {"children": [{"id": "4"}]}
+        ]"#;
+        let tree = NotesTree::from_reader(text.as_bytes()).unwrap();
+        let ids: Vec<&str> = tree.iter().filter_map(|note| note.id.as_deref()).collect();
+        assert_eq!(ids, vec!["1", "2", "3", "4"]);
+        let paths: Vec<(usize, String)> = tree.iter_with_path().map(|(_, path)| (path.depth(), path.to_string())).collect();
+        let expected = vec![(0, ""), (2, "Work/<untitled>"), (1, "Work"), (0, "")];
+        assert_eq!(paths, expected.into_iter().map(|(depth, path)| (depth, String::from(path))).collect::<Vec<_>>());
+        assert_eq!(tree.iter_with_path().nth(1).unwrap().1.folders()[0].subject.as_deref(), Some("Work"));
+    }
+
+    #[test]
+    fn test_notes_tree_visit()
+    {
+        let tree = NotesTree::from_reader(fs::File::open("test.json").unwrap()).unwrap();
+        // aggregation over every note
+        let mut deepest = 0;
+        let walked = tree.visit(|_, path| {
+            deepest = deepest.max(path.depth());
+            ControlFlow::<()>::Continue(())
+        });
+        assert_eq!((walked, deepest), (ControlFlow::Continue(()), 1));
+        // a search that stops at the first hit
+        let mut visited = 0;
+        let found = tree.visit(|note, _| {
+            visited += 1;
+            match note.content.as_deref() {
+                Some(content) if content.contains("test") => ControlFlow::Break(note.id.clone()),
+                _ => ControlFlow::Continue(()),
+            }
+        });
+        assert_eq!((found, visited), (ControlFlow::Break(Some(String::from("3"))), 2));
+    }
+
+    #[test]
+    fn test_typed_model()
+    {
+        let text = r#"{"children": [
+            {"id": "1", "subject": "Work", "date_added": "5", "children": [{"id": "2", "content": "a", "children": []}]},
+            {"id": "3", "subject": "Empty", "children": []}
+        ]}"#;
+        let file: NotesFile = serde_json::from_str(text).unwrap();
+        let NotesFile::Tree(root) = &file else {
+            panic!("expected a single tree");
+        };
+        let Entry::Folder(work) = &root.children[0] else {
+            panic!("expected a folder");
+        };
+        assert_eq!((work.subject.as_deref(), work.extra.get("date_added")), (Some("Work"), Some(&json!("5"))));
+        assert!(matches!(&work.children[0], Entry::Note(note) if note.field("content") == Some("a")));
+        assert!(matches!(&root.children[1], Entry::Note(note) if note.extra.get("children") == Some(&json!([]))));
+        // nothing is lost on the way back
+        assert_eq!(serde_json::to_value(&file).unwrap(), serde_json::from_str::<Value>(text).unwrap());
+
+        let error = serde_json::from_str::<NotesFile>(r#"{"children": [{"subject": 5}]}"#).unwrap_err();
+        assert!(error.to_string().contains("invalid type"), "{}", error);
+    }
+
+    #[test]
+    fn test_opml()
+    {
+        let notes = json!({"children": [
+            {"subject": "Work & \"life\"", "children": [
+                {"subject": "<Todo>", "content": "a\nb\tc\u{7}", "children": []},
+                {"subject": "Empty", "children": []},
+            ]},
+            {"id": "9", "content": "loose", "children": []},
+        ]});
+        let expected = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n  <head>\n    <title>Vivaldi notes</title>\n  </head>\n  <body>\n\
+            \x20   <outline text=\"Work &amp; &quot;life&quot;\">\n\
+            \x20     <outline text=\"&lt;Todo&gt;\" _note=\"a&#10;b&#9;c\"/>\n\
+            \x20     <outline text=\"Empty\"/>\n\
+            \x20   </outline>\n\
+            \x20   <outline text=\"9\" _note=\"loose\"/>\n\
+            \x20 </body>\n</opml>";
+        let opts = Options { opml: true, ..Default::default() };
+        let output = run_query(None, &None, &None, &opts, &notes, None).unwrap();
+        assert_eq!(output.as_deref(), Some(expected));
+
+        // a matched folder is exported with everything in it
+        let output = run_query(Some(String::from("subject")), &Some(String::from("Empty")), &None, &opts, &notes, None).unwrap().unwrap();
+        assert!(output.contains("  <body>\n    <outline text=\"Empty\"/>\n  </body>"));
+
+        let notes: Value = serde_json::from_str(&fs::read_to_string("test.json").unwrap()).unwrap();
+        let output = run_query(None, &None, &None, &opts, &notes, None).unwrap().unwrap();
+        assert!(output.contains("    <outline text=\"1\" _note=\"hello&#10;world\"/>\n"), "{}", output);
+        assert!(output.contains("      <outline text=\"3\" _note=\"test\"/>\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_collect_keys()
+    {
+        let notes = json!({"children": [
+            {"id": "1", "subject": "Folder", "type": "folder", "children": [{"id": "2", "content": "a", "dateCreated": 1}]},
+        ]});
+        let mut keys = BTreeSet::new();
+        collect_keys(&notes, &Progress::new(false), &mut keys);
+        assert_eq!(keys.into_iter().collect::<Vec<&str>>(), vec!["children", "content", "dateCreated", "id", "subject", "type"]);
+    }
+
+    #[test]
+    fn test_config()
+    {
+        let config: Config = toml::from_str("notes_file = \"/tmp/Notes\"\nformat = \"yaml\"\ntruncate = 40\ncolor = \"never\"").unwrap();
+        assert_eq!(config.notes_file().as_deref(), Some("/tmp/Notes"));
+        let mut opts = Options { truncate_content: Some(0), ..Default::default() };
+        config.apply(&mut opts);
+        assert!(opts.yaml());
+        // the command line takes precedence
+        assert_eq!((opts.truncate_subject, opts.truncate_content), (Some(40), Some(0)));
+        assert_eq!(opts.color, Some(Color::Never));
+        assert_eq!(label(opts.color, "error"), "error:");
+        assert_eq!(label(Some(Color::Always), "warning"), "\x1b[1;33mwarning:\x1b[0m");
+
+        let mut opts = Options::default();
+        Config::default().apply(&mut opts);
+        assert!(!opts.yaml() && opts.truncate_subject.is_none() && opts.color.is_none());
+
+        assert!(toml::from_str::<Config>("truncate = \"long\"").is_err());
+        // misspelled settings are reported instead of ignored
+        assert!(toml::from_str::<Config>("colour = \"never\"").is_err());
+    }
+
+    #[test]
+    fn test_command()
+    {
+        command().debug_assert();
+
+        let version_vec = vec!["V", "--version"];
+        assert!(matches!(parse_args(get_string_iter(&version_vec), None), Args::Version(version) if version.contains(env!("CARGO_PKG_VERSION"))));
+
+        // unknown flags are reported instead of ignored
+        let unknown_vec = vec!["V", "-k", "subject", "--bogus", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&unknown_vec), None), Args::BadArgs(reason) if reason.contains("--bogus")));
+        let number_vec = vec!["V", "--head", "two"];
+        assert!(matches!(parse_args(get_string_iter(&number_vec), None), Args::BadArgs(_)));
+
+        // options may follow the file
+        let after_vec = vec!["V", "test.json", "-k", "subject", "-v", "Test"];
+        assert!(matches!(parse_args(get_string_iter(&after_vec), None),
+            Args::Key { key: Some(key), input: Input::File(file), .. } if key == "subject" && file == "test.json"));
+
+        let search_vec = vec!["V", "search", "-k", "subject", "-c", "Test", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&search_vec), None), Args::Key { input: Input::File(_), opts, .. } if opts.all));
+        let search_vec = vec!["V", "search", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&search_vec), None), Args::BadArgs(_)));
+        let get_vec = vec!["V", "get", "--path", "Folder/Note", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&get_vec), None), Args::Key { opts, .. } if !opts.all && opts.note_path.is_some()));
+        let summary_vec = vec!["V", "summary", "--head", "2", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&summary_vec), None), Args::Key { key: None, opts, .. } if opts.head == Some(2)));
+        let summary_vec = vec!["V", "summary", "-k", "id", "-v", "1"];
+        assert!(matches!(parse_args(get_string_iter(&summary_vec), None), Args::BadArgs(_)));
+        let export_vec = vec!["V", "export", "--format", "yaml", "test.json"];
+        assert!(matches!(parse_args(get_string_iter(&export_vec), None), Args::Key { opts, .. } if opts.yaml() && opts.pointer.as_deref() == Some("")));
+        // an explicit json keeps the config file from choosing yaml, the default does not
+        let config: Config = toml::from_str("format = \"yaml\"").unwrap();
+        let export_vec = vec!["V", "export", "--format", "json"];
+        let Args::Key { mut opts, .. } = parse_args(get_string_iter(&export_vec), None) else {
+            panic!("expected Args::Key");
+        };
+        config.apply(&mut opts);
+        assert!(!opts.yaml());
+        let Args::Key { mut opts, .. } = parse_args(get_string_iter(&vec!["V", "export"]), None) else {
+            panic!("expected Args::Key");
+        };
+        config.apply(&mut opts);
+        assert!(opts.yaml());
+        let export_vec = vec!["V", "export", "--format", "flat"];
+        assert!(matches!(parse_args(get_string_iter(&export_vec), None), Args::Key { opts, .. } if opts.flatten));
+        let export_vec = vec!["V", "export", "--format", "csv"];
+        assert!(matches!(parse_args(get_string_iter(&export_vec), None), Args::BadArgs(_)));
+
+        let completions_vec = vec!["V", "completions", "zsh"];
+        assert!(matches!(parse_args(get_string_iter(&completions_vec), None), Args::Completions(Shell::Zsh)));
+        let completions_vec = vec!["V", "completions"];
+        assert!(matches!(parse_args(get_string_iter(&completions_vec), None), Args::BadArgs(_)));
+        let mut script = Vec::new();
+        clap_complete::generate(Shell::Bash, &mut command(), "vivaldi_notes_parser", &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("--ignore-case") && script.contains("vivaldi5"));
+    }
+
+    #[test]
+    #[allow(clippy::assertions_on_constants)]
+    fn test_parse_args()
+    {
+        let help_vec = vec!["V", "-h"];
+        let mut help_args: Box<dyn Iterator<Item = String>> = get_string_iter(&help_vec);
+        assert_eq!(help_args.next(), Some(String::from("V")));
+        assert_eq!(help_args.next(), Some(String::from("-h")));
+
+        let help_args: Box<dyn Iterator<Item = String>> = get_string_iter(&help_vec);
+        let help_args_parsed = parse_args(help_args, None);
+        if let Args::Help(_) = help_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // No args present should run the summary over stdin
+        let summary_vec = vec!["V"];
+        let summary_args: Box<dyn Iterator<Item = String>> = get_string_iter(&summary_vec);
+        let summary_args_parsed = parse_args(summary_args, None);
+        if let Args::Key { key: None, input: Input::Stdin, .. } = summary_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // Even with other args present, -h always shows help
+        let help_key_vec = vec!["V", "-k", "key", "-h"];
+        let help_key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&help_key_vec);
+        let help_key_args_parsed = parse_args(help_key_args, None);
+        if let Args::Help(_) = help_key_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // -k, -v, and -c is rejected as a bad argument (only one of -v or -c)
+        let val_contains_vec = vec!["V", "-k", "key", "-v", "value", "-c", "contents"];
+        let val_contains_args: Box<dyn Iterator<Item = String>> = get_string_iter(&val_contains_vec);
+        let val_contains_args_parsed = parse_args(val_contains_args, None);
+        if let Args::BadArgs(_) = val_contains_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // -v without -k is rejected as a bad argument
+        let val_only_vec = vec!["V", "-v", "value"];
+        let val_only_args: Box<dyn Iterator<Item = String>> = get_string_iter(&val_only_vec);
+        let val_only_args_parsed = parse_args(val_only_args, None);
+        if let Args::BadArgs(_) = val_only_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // -c without -k is rejected as a bad argument
+        let contains_only_vec = vec!["V", "-c", "contents"];
+        let contains_only_args: Box<dyn Iterator<Item = String>> = get_string_iter(&contains_only_vec);
+        let contains_only_args_parsed = parse_args(contains_only_args, None);
+        if let Args::BadArgs(_) = contains_only_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // -k with no key given is rejected as a bad argument
+        let help_key_vec = vec!["V", "-k"];
+        let help_key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&help_key_vec);
+        let help_key_args_parsed = parse_args(help_key_args, None);
+        if let Args::BadArgs(_) = help_key_args_parsed {
+            assert!(true);
+        } else {
+            assert!(false);
+        }
+
+        // -k, -v, and no file
+        let key_vec = vec!["V", "-k", "key", "-v", "value"];
+        let key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&key_vec);
+        let key_args_parsed = parse_args(key_args, None);
+        if let Args::Key {key, val, contains, input, ..} = key_args_parsed {
+            assert_eq!(key, Some(String::from("key")));
+            assert_eq!(val, Some(String::from("value")));
+            assert_eq!(contains, None);
+            if let Input::Stdin = input {
+                assert!(true);
+            } else {
+                assert!(false);
+            }
+        } else {
+            assert!(false);
+        }
+
+        // a file argument comes before the environment variable, which comes before stdin
+        let env_file = || Some(String::from("env.json"));
+        let file_vec = vec!["V", "-k", "key", "test.json"];
+        let file_args: Box<dyn Iterator<Item = String>> = get_string_iter(&file_vec);
+        assert!(matches!(parse_args(file_args, env_file()), Args::Key { input: Input::File(file), .. } if file == "test.json"));
+        let env_vec = vec!["V", "-k", "key"];
+        let env_args: Box<dyn Iterator<Item = String>> = get_string_iter(&env_vec);
+        assert!(matches!(parse_args(env_args, env_file()), Args::Key { input: Input::File(file), .. } if file == "env.json"));
+        let stdin_vec = vec!["V", "-k", "key", "-"];
+        let stdin_args: Box<dyn Iterator<Item = String>> = get_string_iter(&stdin_vec);
+        assert!(matches!(parse_args(stdin_args, env_file()), Args::Key { input: Input::Stdin, .. }));
+        let env_args: Box<dyn Iterator<Item = String>> = get_string_iter(&env_vec);
+        assert!(matches!(parse_args(env_args, Some(String::new())), Args::Key { input: Input::Stdin, .. }));
+        let zip_vec = vec!["V", "--zip", "notes.zip"];
+        let zip_args: Box<dyn Iterator<Item = String>> = get_string_iter(&zip_vec);
+        assert!(matches!(parse_args(zip_args, env_file()), Args::Key { input: Input::Zip { .. }, .. }));
+
+        // -k, -v, and file
+        let key_vec = vec!["V", "-k", "key", "-v", "value", "test.json"];
+        let key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&key_vec);
+        let key_args_parsed = parse_args(key_args, None);
+        if let Args::Key {key, val, contains, input, ..} = key_args_parsed {
+            assert_eq!(key, Some(String::from("key")));
+            assert_eq!(val, Some(String::from("value")));
+            assert_eq!(contains, None);
+            if let Input::File(file) = input {
+                assert_eq!(file, String::from("test.json"));
+            } else {
+                assert!(false);
+            }
+        } else {
+            assert!(false);
+        }
+
+        // -k, -c, and no file
+        let key_vec = vec!["V", "-k", "key", "-c", "contents"];
+        let key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&key_vec);
+        let key_args_parsed = parse_args(key_args, None);
+        if let Args::Key {key, val, contains, input, ..} = key_args_parsed {
+            assert_eq!(key, Some(String::from("key")));
+            assert_eq!(val, None);
+            assert_eq!(contains, Some(String::from("contents")));
+            if let Input::Stdin = input {
+                assert!(true);
+            } else {
+                assert!(false);
+            }
+        } else {
+            assert!(false);
+        }
+
+        // -k, -c, and file
+        let key_vec = vec!["V", "-k", "key", "-c", "contents", "test.json"];
+        let key_args: Box<dyn Iterator<Item = String>> = get_string_iter(&key_vec);
+        let key_args_parsed = parse_args(key_args, None);
+        if let Args::Key {key, val, contains, input, ..} = key_args_parsed {
+            assert_eq!(key, Some(String::from("key")));
+            assert_eq!(val, None);
+            assert_eq!(contains, Some(String::from("contents")));
+            if let Input::File(file) = input {
+                assert_eq!(file, String::from("test.json"));
+            } else {
+                assert!(false);
+            }
+        } else {
+            assert!(false);
+        }
+
+        // --any-field with -c does not need -k
+        let any_vec = vec!["V", "--any-field", "-c", "Todo"];
+        let any_args: Box<dyn Iterator<Item = String>> = get_string_iter(&any_vec);
+        if let Args::Key {key, contains, opts, ..} = parse_args(any_args, None) {
+            assert_eq!(key, None);
+            assert_eq!(contains, Some(String::from("Todo")));
+            assert!(opts.any_field);
+        } else {
+            panic!("expected Args::Key");
+        }
+
+        // --any-field with -v does not need -k either
+        let any_vec = vec!["V", "--any-field", "-v", "Todo"];
+        let any_args: Box<dyn Iterator<Item = String>> = get_string_iter(&any_vec);
+        assert!(matches!(parse_args(any_args, None), Args::Key { key: None, .. }));
+
+        // --any-field without -v or -c is rejected as a bad argument
+        let any_vec = vec!["V", "--any-field"];
+        let any_args: Box<dyn Iterator<Item = String>> = get_string_iter(&any_vec);
+        assert!(matches!(parse_args(any_args, None), Args::BadArgs(_)));
+
+        // --any-field together with -k is rejected as a bad argument
+        let any_vec = vec!["V", "-k", "subject", "--any-field", "-c", "Todo"];
+        let any_args: Box<dyn Iterator<Item = String>> = get_string_iter(&any_vec);
+        assert!(matches!(parse_args(any_args, None), Args::BadArgs(_)));
+
+        // --rank only makes sense with --all
+        let rank_vec = vec!["V", "-k", "content", "-c", "Todo", "--rank"];
+        let rank_args: Box<dyn Iterator<Item = String>> = get_string_iter(&rank_vec);
+        assert!(matches!(parse_args(rank_args, None), Args::BadArgs(_)));
+
+        // --zip with an entry
+        let zip_vec = vec!["V", "-k", "id", "-v", "1", "--zip", "backup.zip", "--entry", "Notes"];
+        let zip_args: Box<dyn Iterator<Item = String>> = get_string_iter(&zip_vec);
+        if let Args::Key {input: Input::Zip { archive, entry }, ..} = parse_args(zip_args, None) {
+            assert_eq!(archive, "backup.zip");
+            assert_eq!(entry, Some(String::from("Notes")));
+        } else {
+            panic!("expected zip input");
+        }
+
+        // --entry without --zip and --zip with a file is rejected as a bad argument
+        let zip_vec = vec!["V", "-k", "id", "-v", "1", "--entry", "Notes"];
+        let zip_args: Box<dyn Iterator<Item = String>> = get_string_iter(&zip_vec);
+        assert!(matches!(parse_args(zip_args, None), Args::BadArgs(_)));
+        let zip_vec = vec!["V", "-k", "id", "-v", "1", "--zip", "backup.zip", "test.json"];
+        let zip_args: Box<dyn Iterator<Item = String>> = get_string_iter(&zip_vec);
+        assert!(matches!(parse_args(zip_args, None), Args::BadArgs(_)));
+
+        // --files-from reads the list of notes files
+        let files_vec = vec!["V", "-k", "id", "-v", "1", "--files-from", "-"];
+        let files_args: Box<dyn Iterator<Item = String>> = get_string_iter(&files_vec);
+        assert!(matches!(parse_args(files_args, None), Args::Key { input: Input::FilesFrom(_), .. }));
+        let files_vec = vec!["V", "-k", "id", "-v", "1", "--files-from", "-", "test.json"];
+        let files_args: Box<dyn Iterator<Item = String>> = get_string_iter(&files_vec);
+        assert!(matches!(parse_args(files_args, None), Args::BadArgs(_)));
+
+        // --unique only makes sense with --all
+        let unique_vec = vec!["V", "-k", "content", "-c", "Todo", "--unique"];
+        let unique_args: Box<dyn Iterator<Item = String>> = get_string_iter(&unique_vec);
+        assert!(matches!(parse_args(unique_args, None), Args::BadArgs(_)));
+
+        // --since with an invalid duration is rejected as a bad argument
+        let since_vec = vec!["V", "-k", "subject", "-c", "Todo", "--since", "7w"];
+        let since_args: Box<dyn Iterator<Item = String>> = get_string_iter(&since_vec);
+        if let Args::BadArgs(reason) = parse_args(since_args, None) {
+            assert!(reason.contains("invalid duration '7w'"));
+        } else {
+            panic!("expected Args::BadArgs");
+        }
+
+        // --since with a valid duration sets a cutoff in the past
+        let since_vec = vec!["V", "-k", "subject", "-c", "Todo", "--since", "7d"];
+        let since_args: Box<dyn Iterator<Item = String>> = get_string_iter(&since_vec);
+        if let Args::Key {opts, ..} = parse_args(since_args, None) {
+            assert!(opts.modified_since.is_some_and(|since| since < chrome_now()));
+        } else {
+            panic!("expected Args::Key");
+        }
+
+        // --keep-going is another name for --ignore-missing-file
+        let keep_vec = vec!["V", "-k", "id", "-v", "1", "--keep-going", "--files-from", "-"];
+        let keep_args: Box<dyn Iterator<Item = String>> = get_string_iter(&keep_vec);
+        assert!(matches!(parse_args(keep_args, None), Args::Key { opts: Options { keep_going: true, .. }, .. }));
+
+        // --truncate sets both lengths, the others only their own field
+        let truncate_vec = vec!["V", "--truncate", "10", "--truncate-content", "0"];
+        let truncate_args: Box<dyn Iterator<Item = String>> = get_string_iter(&truncate_vec);
+        if let Args::Key {opts, ..} = parse_args(truncate_args, None) {
+            assert_eq!((opts.truncate_subject, opts.truncate_content), (Some(10), Some(0)));
+        } else {
+            panic!("expected Args::Key");
+        }
+
+        // --min and --max replace -v and -c, but still need -k
+        let range_vec = vec!["V", "-k", "priority", "--min", "2.5", "--max", "5"];
+        let range_args: Box<dyn Iterator<Item = String>> = get_string_iter(&range_vec);
+        assert!(matches!(parse_args(range_args, None), Args::Key { opts: Options { min_value: Some(_), max_value: Some(_), .. }, .. }));
+        let range_vec = vec!["V", "--min", "2"];
+        let range_args: Box<dyn Iterator<Item = String>> = get_string_iter(&range_vec);
+        assert!(matches!(parse_args(range_args, None), Args::BadArgs(_)));
+        let range_vec = vec!["V", "-k", "priority", "-v", "2", "--max", "5"];
+        let range_args: Box<dyn Iterator<Item = String>> = get_string_iter(&range_vec);
+        assert!(matches!(parse_args(range_args, None), Args::BadArgs(_)));
+
+        // the last of --pager and --no-pager wins
+        let pager_vec = vec!["V", "--pager", "--no-pager"];
+        let pager_args: Box<dyn Iterator<Item = String>> = get_string_iter(&pager_vec);
+        assert!(matches!(parse_args(pager_args, None), Args::Key { opts: Options { pager: Pager::Never, .. }, .. }));
+
+        // -a is short for --all
+        let all_vec = vec!["V", "-a", "-k", "subject", "-v", "Todo", "test.json"];
+        let all_args: Box<dyn Iterator<Item = String>> = get_string_iter(&all_vec);
+        assert!(matches!(parse_args(all_args, None), Args::Key { opts: Options { all: true, .. }, .. }));
+
+        // --first only documents the default and conflicts with the other result modes
+        let first_vec = vec!["V", "--first", "-k", "subject", "-v", "Todo", "test.json"];
+        let first_args: Box<dyn Iterator<Item = String>> = get_string_iter(&first_vec);
+        assert!(matches!(parse_args(first_args, None), Args::Key { opts: Options { first: true, .. }, .. }));
+        for conflict in [&["--all"][..], &["--last"], &["--sample", "2"]] {
+            let mut first_vec = vec!["V", "--first"];
+            first_vec.extend_from_slice(conflict);
+            first_vec.extend_from_slice(&["-k", "subject", "-v", "Todo", "test.json"]);
+            let first_args: Box<dyn Iterator<Item = String>> = get_string_iter(&first_vec);
+            assert!(matches!(parse_args(first_args, None), Args::BadArgs(_)), "{:?}", conflict);
+        }
+
+        // --nth counts from 1 and picks a single match
+        let nth_vec = vec!["V", "--nth", "0", "-k", "subject", "-v", "Todo", "test.json"];
+        let nth_args: Box<dyn Iterator<Item = String>> = get_string_iter(&nth_vec);
+        assert!(matches!(parse_args(nth_args, None), Args::BadArgs(_)));
+        let nth_vec = vec!["V", "--nth", "2", "--all", "-k", "subject", "-v", "Todo", "test.json"];
+        let nth_args: Box<dyn Iterator<Item = String>> = get_string_iter(&nth_vec);
+        assert!(matches!(parse_args(nth_args, None), Args::BadArgs(_)));
+
+        // --replace takes two values and writes back to a file
+        let replace_vec = vec!["V", "-k", "subject", "-v", "Todo", "--replace", "a", "b", "test.json"];
+        let replace_args: Box<dyn Iterator<Item = String>> = get_string_iter(&replace_vec);
+        if let Args::Key {opts, input: Input::File(_), ..} = parse_args(replace_args, None) {
+            assert_eq!(opts.replace, Some((String::from("a"), String::from("b"))));
+        } else {
+            panic!("expected Args::Key");
+        }
+        let replace_vec = vec!["V", "-k", "subject", "-v", "Todo", "--replace", "a", "b"];
+        let replace_args: Box<dyn Iterator<Item = String>> = get_string_iter(&replace_vec);
+        assert!(matches!(parse_args(replace_args, None), Args::BadArgs(_)));
+        let replace_vec = vec!["V", "-k", "subject", "-v", "Todo", "--replace", "a"];
+        let replace_args: Box<dyn Iterator<Item = String>> = get_string_iter(&replace_vec);
+        assert!(matches!(parse_args(replace_args, None), Args::BadArgs(_)));
+
+        // the content length bounds must be non-negative
+        let len_vec = vec!["V", "-k", "subject", "-c", "Todo", "--max-len", "-1"];
+        let len_args: Box<dyn Iterator<Item = String>> = get_string_iter(&len_vec);
+        assert!(matches!(parse_args(len_args, None), Args::BadArgs(_)));
+    }
+}